@@ -0,0 +1,493 @@
+use crate::nfa::class::Class;
+use crate::nfa::glushkov::glushkov;
+use crate::nfa::look::Look;
+use crate::nfa::{
+    any, class, concat_all, empty, intersect, look, plus, repeat, star, thompson, try_complement,
+    unit, Construction, NFA,
+};
+use std::fmt;
+
+/// A parsed pattern, sitting between the pattern string and the compiled
+/// `NFA`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Expr {
+    /// Matches only the empty string.
+    Empty,
+    Literal(char),
+    /// A bracketed class such as `[a-z_]`, or `[^a-z_]` when `negated`. The
+    /// class holds the listed chars, before any negation.
+    Class {
+        negated: bool,
+        class: Class,
+    },
+    /// The wildcard `.`, which matches `\n` only if `dot_all`.
+    Any {
+        dot_all: bool,
+    },
+    /// A zero-width assertion: `^`, `$`, `\b` or `\B`.
+    Look(Look),
+    Concat(Vec<Expr>),
+    Alt(Vec<Expr>),
+    /// The strings matched by every one of the expressions, written `a&b`.
+    Intersect(Vec<Expr>),
+    /// The strings not matched by the expression, written `~(...)`.
+    Complement(Box<Expr>),
+    Star(Box<Expr>),
+    /// Between `min` and `max` repetitions, or at least `min` if `max` is
+    /// `None`. Written `+`, `?`, `{m}`, `{m,}` or `{m,n}`.
+    Repeat {
+        expr: Box<Expr>,
+        min: usize,
+        max: Option<usize>,
+    },
+    /// A capturing group `(...)`, or `(?<name>...)` if named, numbered from 1
+    /// in the order of the opening parentheses. Non-capturing `(?:...)` groups
+    /// leave no trace in the tree.
+    Group {
+        index: usize,
+        name: Option<String>,
+        expr: Box<Expr>,
+    },
+}
+
+impl Expr {
+    /// Lowers the expression onto the combinators in `nfa`.
+    pub fn to_nfa(&self) -> NFA {
+        self.to_nfa_with(Construction::default())
+    }
+
+    /// Lowers the expression onto the combinators of `construction`.
+    ///
+    /// A complement determinizes its operand, which can take exponentially
+    /// many states, as for `~((a|b)*a(a|b){20})`. `try_to_nfa_with` bounds
+    /// that work.
+    pub fn to_nfa_with(&self, construction: Construction) -> NFA {
+        self.try_to_nfa_with(construction, usize::MAX)
+            .expect("no limit on the states of a complement")
+    }
+
+    /// Like `to_nfa_with`, but gives up on any complement whose operand would
+    /// determinize into more than `max_states` states. Fails with the index
+    /// of that complement, counting from 0 in the order of their `~(` in the
+    /// pattern.
+    pub fn try_to_nfa_with(
+        &self,
+        construction: Construction,
+        max_states: usize,
+    ) -> Result<NFA, usize> {
+        let mut lowering = Lowering {
+            construction,
+            max_states,
+            complements: 0,
+        };
+        lowering.lower(self)
+    }
+
+    /// Whether the expression uses `&` or `~(...)` anywhere.
+    pub fn has_set_operators(&self) -> bool {
+        match self {
+            Expr::Intersect(_) | Expr::Complement(_) => true,
+            Expr::Concat(exprs) | Expr::Alt(exprs) => exprs.iter().any(Expr::has_set_operators),
+            Expr::Star(expr) | Expr::Repeat { expr, .. } | Expr::Group { expr, .. } => {
+                expr.has_set_operators()
+            }
+            Expr::Empty
+            | Expr::Literal(_)
+            | Expr::Class { .. }
+            | Expr::Any { .. }
+            | Expr::Look(_) => false,
+        }
+    }
+
+    /// How tightly the printed form of this expression binds, so that
+    /// `Display` knows when a subexpression needs parentheses.
+    fn precedence(&self) -> u8 {
+        match self {
+            Expr::Concat(exprs) | Expr::Alt(exprs) | Expr::Intersect(exprs) if exprs.len() == 1 => {
+                exprs[0].precedence()
+            }
+            Expr::Alt(exprs) if exprs.len() > 1 => 0,
+            Expr::Intersect(exprs) if exprs.len() > 1 => 1,
+            Expr::Empty | Expr::Concat(_) | Expr::Alt(_) | Expr::Intersect(_) => 2,
+            Expr::Star(_) | Expr::Repeat { .. } => 3,
+            Expr::Literal(_)
+            | Expr::Class { .. }
+            | Expr::Any { .. }
+            | Expr::Look(_)
+            | Expr::Group { .. }
+            | Expr::Complement(_) => 4,
+        }
+    }
+
+    fn fmt_at(&self, f: &mut fmt::Formatter, precedence: u8) -> fmt::Result {
+        if self.precedence() < precedence {
+            write!(f, "(?:")?;
+            self.fmt_at(f, 0)?;
+            return write!(f, ")");
+        }
+        match self {
+            Expr::Empty => Ok(()),
+            Expr::Literal(ch) => write_char(f, *ch, is_meta(*ch)),
+            Expr::Class { negated, class } => {
+                write!(f, "[")?;
+                if *negated {
+                    write!(f, "^")?;
+                }
+                for &(lo, hi) in class.ranges() {
+                    write_char(f, lo, is_class_meta(lo))?;
+                    if hi > lo {
+                        write!(f, "-")?;
+                        write_char(f, hi, is_class_meta(hi))?;
+                    }
+                }
+                write!(f, "]")
+            }
+            // `.` means "anything but a newline" when read back with the
+            // default options, so the dot-all wildcard is spelled as the class
+            // of every char instead
+            Expr::Any { dot_all: false } => write!(f, "."),
+            Expr::Any { dot_all: true } => write!(f, "[^]"),
+            Expr::Look(look) => match look {
+                Look::Start => write!(f, "^"),
+                Look::End => write!(f, "$"),
+                Look::StartLine => write!(f, "(?m:^)"),
+                Look::EndLine => write!(f, "(?m:$)"),
+                Look::WordBoundary => write!(f, "\\b"),
+                Look::NotWordBoundary => write!(f, "\\B"),
+            },
+            // nested concatenations, alternations and intersections are
+            // associative, so they never need parentheses of their own
+            Expr::Concat(exprs) => exprs.iter().try_for_each(|expr| match expr {
+                Expr::Concat(_) => expr.fmt_at(f, 2),
+                _ => expr.fmt_at(f, 3),
+            }),
+            Expr::Alt(exprs) => {
+                for (i, expr) in exprs.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, "|")?;
+                    }
+                    match expr {
+                        Expr::Alt(_) => expr.fmt_at(f, 0)?,
+                        _ => expr.fmt_at(f, 1)?,
+                    }
+                }
+                Ok(())
+            }
+            Expr::Intersect(exprs) => {
+                for (i, expr) in exprs.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, "&")?;
+                    }
+                    match expr {
+                        Expr::Intersect(_) => expr.fmt_at(f, 1)?,
+                        _ => expr.fmt_at(f, 2)?,
+                    }
+                }
+                Ok(())
+            }
+            Expr::Star(expr) => {
+                expr.fmt_at(f, 4)?;
+                write!(f, "*")
+            }
+            Expr::Repeat { expr, min, max } => {
+                expr.fmt_at(f, 4)?;
+                match (min, max) {
+                    (1, None) => write!(f, "+"),
+                    (0, Some(1)) => write!(f, "?"),
+                    (min, None) => write!(f, "{{{},}}", min),
+                    (min, Some(max)) if min == max => write!(f, "{{{}}}", min),
+                    (min, Some(max)) => write!(f, "{{{},{}}}", min, max),
+                }
+            }
+            Expr::Complement(expr) => {
+                write!(f, "~(")?;
+                expr.fmt_at(f, 0)?;
+                write!(f, ")")
+            }
+            Expr::Group { name, expr, .. } => {
+                write!(f, "(")?;
+                if let Some(name) = name {
+                    write!(f, "?<{}>", name)?;
+                }
+                expr.fmt_at(f, 0)?;
+                write!(f, ")")
+            }
+        }
+    }
+}
+
+/// The state of lowering an expression onto an automaton.
+struct Lowering {
+    construction: Construction,
+    max_states: usize,
+    /// How many complements have been reached so far.
+    complements: usize,
+}
+
+impl Lowering {
+    fn lower(&mut self, mut expr: &Expr) -> Result<NFA, usize> {
+        // a group only matters for captures, so skip it without recursing
+        while let Expr::Group { expr: inner, .. } = expr {
+            expr = inner;
+        }
+        if self.construction == Construction::Glushkov && !expr.has_set_operators() {
+            return Ok(glushkov(expr));
+        }
+        let thompson = self.construction == Construction::Thompson;
+        Ok(match expr {
+            Expr::Empty => empty(),
+            Expr::Literal(ch) => unit(*ch),
+            Expr::Class {
+                negated,
+                class: chars,
+            } => class(if *negated {
+                chars.negate()
+            } else {
+                chars.clone()
+            }),
+            Expr::Concat(exprs) => {
+                let nfas = self.lower_all(exprs)?;
+                let parts: Vec<&NFA> = nfas.iter().collect();
+                if thompson {
+                    thompson::concat_all(&parts)
+                } else {
+                    concat_all(&parts)
+                }
+            }
+            Expr::Alt(exprs) => {
+                let mut nfas = self.lower_all(exprs)?.into_iter();
+                let first = nfas.next().unwrap_or_else(empty);
+                if thompson {
+                    nfas.fold(first, |nfa, other| thompson::plus(&nfa, &other))
+                } else {
+                    nfas.fold(first, |nfa, other| plus(&nfa, &other))
+                }
+            }
+            Expr::Intersect(exprs) => {
+                let mut nfas = self.lower_all(exprs)?.into_iter();
+                let first = nfas.next().unwrap_or_else(empty);
+                nfas.fold(first, |nfa, other| intersect(&nfa, &other))
+            }
+            Expr::Complement(expr) => {
+                // numbered before the complements inside it, whose `~(` come
+                // later in the pattern
+                let index = self.complements;
+                self.complements += 1;
+                let nfa = self.lower(expr)?;
+                try_complement(&nfa, self.max_states).ok_or(index)?
+            }
+            Expr::Any { dot_all } => any(*dot_all),
+            Expr::Look(l) => look(*l),
+            Expr::Star(expr) if thompson => thompson::star(&self.lower(expr)?),
+            Expr::Star(expr) => star(&self.lower(expr)?),
+            Expr::Repeat { expr, min, max } if thompson => {
+                thompson::repeat(&self.lower(expr)?, *min, *max)
+            }
+            Expr::Repeat { expr, min, max } => repeat(&self.lower(expr)?, *min, *max),
+            Expr::Group { .. } => unreachable!("groups are skipped above"),
+        })
+    }
+
+    fn lower_all(&mut self, exprs: &[Expr]) -> Result<Vec<NFA>, usize> {
+        exprs.iter().map(|expr| self.lower(expr)).collect()
+    }
+}
+
+/// Characters that must be escaped to be matched literally.
+pub fn is_meta(ch: char) -> bool {
+    matches!(
+        ch,
+        '(' | ')' | '|' | '&' | '~' | '*' | '+' | '?' | '{' | '[' | '.' | '^' | '$' | '\\'
+    )
+}
+
+/// Characters that must be escaped to be matched literally inside a class.
+pub fn is_class_meta(ch: char) -> bool {
+    matches!(ch, '[' | ']' | '^' | '-' | '\\')
+}
+
+/// Writes `ch` as it would appear in a pattern, escaping it if `special` and
+/// spelling out whitespace and control characters.
+pub(crate) fn write_char<W: fmt::Write>(f: &mut W, ch: char, special: bool) -> fmt::Result {
+    match ch {
+        '\n' => write!(f, "\\n"),
+        '\r' => write!(f, "\\r"),
+        '\t' => write!(f, "\\t"),
+        ch if ch.is_control() => write!(f, "\\u{{{:x}}}", ch as u32),
+        ch if special => write!(f, "\\{}", ch),
+        ch => write!(f, "{}", ch),
+    }
+}
+
+/// Prints the expression back as a canonical pattern: unnecessary escapes are
+/// dropped and non-capturing groups are only added where the structure
+/// requires them.
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.fmt_at(f, 0)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::ast::*;
+    use crate::parse::{parse, parse_with, Options};
+    use char_stream::CharStream;
+
+    fn round_trip(pattern: &str) -> String {
+        parse(pattern).unwrap().to_string()
+    }
+
+    #[test]
+    pub fn test_complement_state_limit() {
+        let expr = parse("~(a)|~((a|b)*a(a|b){3})").unwrap();
+        assert_eq!(
+            expr.try_to_nfa_with(Construction::default(), 8).err(),
+            Some(1)
+        );
+        assert!(expr.try_to_nfa_with(Construction::default(), 1000).is_ok());
+        // no limit without asking for one
+        let expr = parse("~((a|b)*a(a|b){9})").unwrap();
+        assert!(expr
+            .to_nfa_with(Construction::Thompson)
+            .is_match(&mut CharStream::from("b")));
+    }
+
+    #[test]
+    pub fn test_parse_tree() {
+        let expr = parse("(ab|c)*d").unwrap();
+        assert_eq!(
+            expr,
+            Expr::Concat(vec![
+                Expr::Star(Box::new(Expr::Group {
+                    index: 1,
+                    name: None,
+                    expr: Box::new(Expr::Alt(vec![
+                        Expr::Concat(vec![Expr::Literal('a'), Expr::Literal('b')]),
+                        Expr::Literal('c'),
+                    ])),
+                })),
+                Expr::Literal('d'),
+            ])
+        );
+    }
+
+    #[test]
+    pub fn test_round_trip() {
+        for pattern in [
+            "",
+            "a",
+            "ab|c|",
+            "(ab|c)*d",
+            "((a))",
+            r"\(\*\|\\\[",
+            "(|a)*",
+            "[0-9A-Z_a-z]*",
+            r"[^\n\-\^]",
+            "[]|[^]",
+            "a+(bc)?d{0,}e{2}f{2,3}",
+            r"\+\?\{}",
+            "(a*)+",
+            r"^\bfoo\B$",
+            r"(?m:^)\^\$(?m:$)",
+            "(?:a|b)c(?:)*",
+            "(?<year>[0-9]{4})-(?<month>[0-9]{2})",
+            "a&b|c&d&",
+            r"(?:a|b)&(?:c&d)*\&",
+            r"~(a|b)c*~()\~",
+            "~(a&~(b))*",
+        ] {
+            assert_eq!(round_trip(pattern), pattern);
+        }
+    }
+
+    #[test]
+    pub fn test_canonical() {
+        assert_eq!(round_trip(r"\a\-"), "a-");
+        assert_eq!(round_trip("(?m:^a|b$)"), "(?m:^)a|b(?m:$)");
+        assert_eq!(round_trip("()"), "()");
+        assert_eq!(round_trip("(?:a)(?:)"), "a");
+        assert_eq!(round_trip("(?P<user>a)"), "(?<user>a)");
+        assert_eq!(round_trip("[c-ea-c_]"), "[_a-e]");
+        assert_eq!(round_trip(r"[\]\n]\u{7}"), r"[\n\]]\u{7}");
+    }
+
+    #[test]
+    pub fn test_display_any() {
+        let options = Options {
+            dot_all: true,
+            ..Options::default()
+        };
+        assert_eq!(parse(r"a.\.").unwrap().to_string(), r"a.\.");
+        assert_eq!(parse_with("a.", options).unwrap().to_string(), "a[^]");
+    }
+
+    #[test]
+    pub fn test_display_adds_parentheses() {
+        let expr = Expr::Star(Box::new(Expr::Concat(vec![
+            Expr::Alt(vec![Expr::Literal('a'), Expr::Literal('b')]),
+            Expr::Literal('c'),
+        ])));
+        assert_eq!(expr.to_string(), "(?:(?:a|b)c)*");
+        let expr = Expr::Concat(vec![
+            Expr::Intersect(vec![Expr::Literal('a'), Expr::Literal('b')]),
+            Expr::Alt(vec![Expr::Literal('c'), Expr::Literal('d')]),
+        ]);
+        assert_eq!(expr.to_string(), "(?:a&b)(?:c|d)");
+        assert_eq!(Expr::Star(Box::new(Expr::Empty)).to_string(), "(?:)*");
+    }
+
+    #[test]
+    pub fn test_to_nfa() {
+        let nfa = parse("(ab|c)*d").unwrap().to_nfa();
+        assert!(nfa.is_match(&mut CharStream::from("abcd")));
+        assert!(!nfa.is_match(&mut CharStream::from("abc")));
+    }
+
+    #[test]
+    pub fn test_to_nfa_with() {
+        for pattern in [
+            "(ab|c)*d",
+            "a{2,3}b?",
+            "(a|)+",
+            r"^\bx|y$",
+            "[^a]*",
+            "(a|b)*&.b|x",
+            "~(a.*)",
+            r"~(\ba)",
+            "(a.&.b)*",
+            "(~(a)&b.){1,2}",
+        ] {
+            let expr = parse(pattern).unwrap();
+            let rewired = expr.to_nfa();
+            let thompson = expr.to_nfa_with(Construction::Thompson);
+            let glushkov = expr.to_nfa_with(Construction::Glushkov);
+            for haystack in [
+                "", "abcd", "d", "abc", "aa", "aaab", "a", "x", "y", "bb", "ab", "abab", "bbba",
+            ] {
+                let expected = rewired.is_match(&mut CharStream::from(haystack));
+                assert_eq!(thompson.is_match(&mut CharStream::from(haystack)), expected);
+                assert_eq!(glushkov.is_match(&mut CharStream::from(haystack)), expected);
+            }
+        }
+    }
+
+    #[test]
+    pub fn test_deepest_nesting() {
+        use crate::parse::MAX_NESTING;
+        use crate::regex::Regex;
+        let pattern = format!("{}a{}", "(".repeat(MAX_NESTING), ")*".repeat(MAX_NESTING));
+        let expr = parse(&pattern).unwrap();
+        assert_eq!(parse(&expr.to_string()).unwrap(), expr);
+        for construction in [
+            Construction::Rewiring,
+            Construction::Thompson,
+            Construction::Glushkov,
+        ] {
+            let nfa = expr.to_nfa_with(construction);
+            assert!(nfa.is_match(&mut CharStream::from("aaa")));
+        }
+        let regex = Regex::new(&pattern).unwrap();
+        assert_eq!(regex.captures("aa").unwrap().get_str(0), Some("aa"));
+    }
+}
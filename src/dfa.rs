@@ -0,0 +1,388 @@
+pub mod alphabet;
+pub mod lazy;
+
+use crate::nfa::look::Side;
+use crate::nfa::node::Node;
+use crate::nfa::NFA;
+use alphabet::Alphabet;
+use char_stream::CharStream;
+use std::collections::{HashMap, HashSet};
+use std::ops::Range;
+
+/// A deterministic automaton over the ranges of an `Alphabet`, with states
+/// numbered from 0 and a dense transition table.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DFA {
+    alphabet: Alphabet,
+    /// The state after reading a char of range `k` in state `s` is at
+    /// `table[s * alphabet.len() + k]`.
+    table: Vec<usize>,
+    accepting: Vec<bool>,
+    start: usize,
+}
+
+/// A state of the subset construction: the nodes the NFA is in before taking
+/// any zero-width edges, and the kind of char just read. The zero-width edges
+/// can only be followed once the next char is known.
+pub(crate) type Subset = (Vec<Node>, Side);
+
+pub(crate) fn start_subset(nfa: &NFA) -> Subset {
+    let mut start: Vec<Node> = nfa.starting().iter().copied().collect();
+    start.sort();
+    (start, Side::Edge)
+}
+
+/// The subset reached from `subset` by reading `ch`. Without zero-width edges
+/// the previous char never matters, so it is then always recorded as
+/// `Side::Edge` to avoid duplicate states.
+pub(crate) fn successor(nfa: &NFA, looks: bool, (nodes, before): &Subset, ch: char) -> Subset {
+    let mut closed: HashSet<Node> = nodes.iter().copied().collect();
+    nfa.close(&mut closed, *before, Side::of(Some(ch)));
+    let mut stepped: Vec<Node> = nfa.step(&closed, ch).into_iter().collect();
+    stepped.sort();
+    let side = if looks {
+        Side::of(Some(ch))
+    } else {
+        Side::Edge
+    };
+    (stepped, side)
+}
+
+/// Whether the input should be accepted if it ends in `subset`.
+pub(crate) fn accepts(nfa: &NFA, (nodes, before): &Subset) -> bool {
+    let mut closed: HashSet<Node> = nodes.iter().copied().collect();
+    nfa.close(&mut closed, *before, Side::Edge);
+    closed.iter().any(|node| nfa.finished().contains(node))
+}
+
+impl DFA {
+    /// Determinizes `nfa` with the powerset construction, keeping only the
+    /// subsets reachable from the start.
+    ///
+    /// There can be exponentially many of those: `(a|b)*a(a|b){n}` needs
+    /// `2^(n+1)` states to remember its last `n + 1` chars.
+    pub fn from_nfa(nfa: &NFA) -> DFA {
+        DFA::from_nfa_within(nfa, usize::MAX).expect("no limit on the states")
+    }
+
+    /// Like `from_nfa`, but gives up with `None` once more than `max_states`
+    /// subsets are reachable.
+    pub fn from_nfa_within(nfa: &NFA, max_states: usize) -> Option<DFA> {
+        let alphabet = Alphabet::of(nfa);
+        let looks = nfa.uses_looks();
+
+        let start = start_subset(nfa);
+        let mut ids: HashMap<Subset, usize> = HashMap::new();
+        ids.insert(start.clone(), 0);
+        let mut subsets: Vec<Subset> = vec![start];
+
+        let mut table = vec![];
+        let mut accepting = vec![];
+        let mut next = 0;
+        while next < subsets.len() {
+            let subset = subsets[next].clone();
+            next += 1;
+            accepting.push(accepts(nfa, &subset));
+            for k in 0..alphabet.len() {
+                let subset = successor(nfa, looks, &subset, alphabet.representative(k));
+                let id = match ids.get(&subset) {
+                    Some(&id) => id,
+                    None if subsets.len() == max_states => return None,
+                    None => {
+                        ids.insert(subset.clone(), subsets.len());
+                        subsets.push(subset);
+                        subsets.len() - 1
+                    }
+                };
+                table.push(id);
+            }
+        }
+
+        Some(DFA {
+            alphabet,
+            table,
+            accepting,
+            start: 0,
+        })
+    }
+
+    /// The equivalent DFA with the fewest states, found by Hopcroft's
+    /// partition refinement. States are numbered in breadth-first order from
+    /// the start, so DFAs for the same language over the same alphabet come
+    /// out identical.
+    pub fn minimize(&self) -> DFA {
+        let n = self.states();
+        let m = self.alphabet.len();
+        // sources[k][t] lists the states that go to `t` on range `k`
+        let mut sources: Vec<Vec<Vec<usize>>> = vec![vec![vec![]; n]; m];
+        for state in 0..n {
+            for (k, sources) in sources.iter_mut().enumerate() {
+                sources[self.next_class(state, k)].push(state);
+            }
+        }
+
+        // every block is a range of `elements`, which lists each state once;
+        // `position` says where
+        let mut elements: Vec<usize> = (0..n).filter(|&state| self.accepting[state]).collect();
+        let accepting = elements.len();
+        elements.extend((0..n).filter(|&state| !self.accepting[state]));
+        let mut position = vec![0; n];
+        for (i, &state) in elements.iter().enumerate() {
+            position[state] = i;
+        }
+        let mut blocks: Vec<Range<usize>> = vec![0..accepting, accepting..n];
+        blocks.retain(|block| !block.is_empty());
+        let mut block_of = vec![0; n];
+        for (b, block) in blocks.iter().enumerate() {
+            for &state in &elements[block.clone()] {
+                block_of[state] = b;
+            }
+        }
+
+        let mut pending: Vec<usize> = (0..blocks.len()).collect();
+        let mut is_pending = vec![true; blocks.len()];
+        // how many states at the end of each block go into the splitter
+        let mut marked = vec![0; blocks.len()];
+        while let Some(splitter) = pending.pop() {
+            is_pending[splitter] = false;
+            let splitter = elements[blocks[splitter].clone()].to_vec();
+            for sources in sources.iter() {
+                // move the states that go into the splitter to the end of
+                // their blocks
+                let mut hit = vec![];
+                for &target in &splitter {
+                    for &state in &sources[target] {
+                        let b = block_of[state];
+                        let first_marked = blocks[b].end - marked[b];
+                        if position[state] >= first_marked {
+                            continue;
+                        }
+                        if marked[b] == 0 {
+                            hit.push(b);
+                        }
+                        let other = elements[first_marked - 1];
+                        elements.swap(position[state], first_marked - 1);
+                        position[other] = position[state];
+                        position[state] = first_marked - 1;
+                        marked[b] += 1;
+                    }
+                }
+                for b in hit {
+                    let inside = std::mem::take(&mut marked[b]);
+                    if inside == blocks[b].len() {
+                        continue;
+                    }
+                    let split = blocks.len();
+                    let middle = blocks[b].end - inside;
+                    for &state in &elements[middle..blocks[b].end] {
+                        block_of[state] = split;
+                    }
+                    blocks.push(middle..blocks[b].end);
+                    blocks[b].end = middle;
+                    marked.push(0);
+                    // refining by either half refines by the whole, so unless
+                    // the whole is still pending only the smaller half is
+                    if is_pending[b] || blocks[split].len() <= blocks[b].len() {
+                        pending.push(split);
+                        is_pending.push(true);
+                    } else {
+                        pending.push(b);
+                        is_pending[b] = true;
+                        is_pending.push(false);
+                    }
+                }
+            }
+        }
+
+        // renumber the blocks breadth first from the start
+        let mut ids: Vec<Option<usize>> = vec![None; blocks.len()];
+        let mut order = vec![block_of[self.start]];
+        ids[block_of[self.start]] = Some(0);
+        let mut table = vec![];
+        let mut next = 0;
+        while next < order.len() {
+            let state = elements[blocks[order[next]].start];
+            next += 1;
+            for k in 0..m {
+                let b = block_of[self.next_class(state, k)];
+                let id = *ids[b].get_or_insert_with(|| {
+                    order.push(b);
+                    order.len() - 1
+                });
+                table.push(id);
+            }
+        }
+        let accepting = order
+            .iter()
+            .map(|&b| self.accepting[elements[blocks[b].start]])
+            .collect();
+
+        DFA {
+            alphabet: self.alphabet.clone(),
+            table,
+            accepting,
+            start: 0,
+        }
+    }
+
+    /// The number of states.
+    pub fn states(&self) -> usize {
+        self.accepting.len()
+    }
+
+    pub fn start(&self) -> usize {
+        self.start
+    }
+
+    pub fn alphabet(&self) -> &Alphabet {
+        &self.alphabet
+    }
+
+    pub fn is_accepting(&self, state: usize) -> bool {
+        self.accepting[state]
+    }
+
+    /// The state reached from `state` by reading a char of range `k`.
+    pub fn next_class(&self, state: usize, k: usize) -> usize {
+        self.table[state * self.alphabet.len() + k]
+    }
+
+    /// The state reached from `state` by reading `ch`.
+    pub fn next(&self, state: usize, ch: char) -> usize {
+        self.next_class(state, self.alphabet.class_of(ch))
+    }
+
+    pub fn is_match(&self, stream: &mut CharStream) -> bool {
+        let mut state = self.start;
+        for ch in stream {
+            state = self.next(state, ch);
+        }
+        self.accepting[state]
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::dfa::*;
+    use crate::parse::Options;
+
+    fn agree(nfa: &NFA, haystacks: &[&str]) {
+        let dfa = DFA::from_nfa(nfa);
+        for haystack in haystacks {
+            assert_eq!(
+                dfa.is_match(&mut CharStream::from(haystack)),
+                nfa.is_match(&mut CharStream::from(haystack)),
+                "{:?}",
+                haystack
+            );
+        }
+    }
+
+    #[test]
+    pub fn test_agrees_with_nfa() {
+        let haystacks = [
+            "", "a", "b", "ab", "ba", "abb", "aabb", "babb", "abab", "x", "a b", "ab\nb", "\n",
+            "ab\n", "a_b", "é", "abé", "aé",
+        ];
+        for pattern in [
+            "",
+            "a",
+            "ab",
+            "a|b",
+            "(a|b)*abb",
+            "a*b*",
+            "[a-c]+",
+            "[^a]*",
+            ".*",
+            "a{2,3}",
+            "(ab)?b",
+            "[]",
+            r"a\b.*",
+            r"\Ba",
+            r"a\B",
+            "^a$",
+            r"(a|\b)*b",
+        ] {
+            agree(&NFA::from_pattern(pattern).unwrap(), &haystacks);
+        }
+        let options = Options {
+            dot_all: true,
+            multi_line: true,
+        };
+        for pattern in ["a.*", "^a|b$", ".*$\n^b"] {
+            agree(
+                &NFA::from_pattern_with(pattern, options).unwrap(),
+                &haystacks,
+            );
+        }
+    }
+
+    #[test]
+    pub fn test_states() {
+        let dfa = DFA::from_nfa(&NFA::from_pattern("(a|b)*abb").unwrap());
+        assert!(dfa.states() >= 5);
+        assert!(!dfa.is_accepting(dfa.start()));
+        let end = "aabb"
+            .chars()
+            .fold(dfa.start(), |state, ch| dfa.next(state, ch));
+        assert!(dfa.is_accepting(end));
+    }
+
+    #[test]
+    pub fn test_from_nfa_within() {
+        let nfa = NFA::from_pattern("(a|b)*abb").unwrap();
+        let states = DFA::from_nfa(&nfa).states();
+        assert_eq!(
+            DFA::from_nfa_within(&nfa, states),
+            Some(DFA::from_nfa(&nfa))
+        );
+        assert_eq!(DFA::from_nfa_within(&nfa, states - 1), None);
+    }
+
+    #[test]
+    pub fn test_minimize() {
+        let dfa = DFA::from_nfa(&NFA::from_pattern("(a|b)*abb").unwrap());
+        let min = dfa.minimize();
+        // four states tracking the suffix read so far, plus one for any other
+        // char
+        assert_eq!(min.states(), 5);
+        assert_eq!(min.minimize(), min);
+        for haystack in ["", "abb", "aabb", "abab", "babb", "abbc", "ab"] {
+            assert_eq!(
+                min.is_match(&mut CharStream::from(haystack)),
+                dfa.is_match(&mut CharStream::from(haystack))
+            );
+        }
+
+        let other = DFA::from_nfa(&NFA::from_pattern("(a*b*)*ab(b|bb(a|b)*abb)").unwrap());
+        assert_ne!(other, dfa);
+        assert_eq!(other.minimize(), min);
+    }
+
+    #[test]
+    pub fn test_minimize_many_splits() {
+        // one state per string of the last seven chars, plus one for any
+        // other char
+        let nfa = NFA::from_pattern("(a|b)*a(a|b){6}").unwrap();
+        let min = DFA::from_nfa(&nfa).minimize();
+        assert_eq!(min.states(), 129);
+        let other = NFA::from_pattern("(a|b)*a(a|b)(a|b){5}").unwrap();
+        assert_eq!(DFA::from_nfa(&other).minimize(), min);
+    }
+
+    #[test]
+    pub fn test_minimize_trivial() {
+        let dfa = DFA::from_nfa(&NFA::from_pattern("[]").unwrap()).minimize();
+        assert_eq!(dfa.states(), 1);
+        assert!(!dfa.is_accepting(dfa.start()));
+        let dfa = DFA::from_nfa(&NFA::from_pattern("a*|[^a]*").unwrap()).minimize();
+        assert_eq!(dfa.states(), 4);
+    }
+
+    #[test]
+    pub fn test_unicode() {
+        let dfa = DFA::from_nfa(&NFA::from_pattern("[α-ω]+!").unwrap());
+        assert!(dfa.is_match(&mut CharStream::from("λογος!")));
+        assert!(!dfa.is_match(&mut CharStream::from("λογοs!")));
+    }
+}
@@ -0,0 +1,140 @@
+use crate::nfa::class::{pred, succ};
+use crate::nfa::look::{word_class, Look};
+use crate::nfa::node::Node;
+use crate::nfa::NFA;
+
+/// A partition of every `char` into contiguous ranges that an `NFA` cannot
+/// tell apart: any two chars in the same range lead to the same nodes from
+/// every node, and look the same to every `Look` on its edges. A DFA then
+/// needs one column per range rather than one per char.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Alphabet {
+    /// The first char of each range, in order, starting with `'\0'`.
+    starts: Vec<char>,
+    /// The range of each ASCII char, to skip the search on the common path.
+    ascii: [usize; 128],
+}
+
+impl Alphabet {
+    /// Splits the chars wherever an edge of `nfa` starts or stops, and, if it
+    /// has zero-width edges, wherever the `Side` of a char changes.
+    pub fn of(nfa: &NFA) -> Alphabet {
+        Alphabet::of_all(&[nfa])
+    }
+
+    /// A partition that none of `nfas` can tell apart, as `of` gives for one.
+    pub fn of_all(nfas: &[&NFA]) -> Alphabet {
+        let mut bounds: Vec<(char, char)> = vec![];
+        for (nfa, node) in nfas
+            .iter()
+            .flat_map(|nfa| (0..nfa.states()).map(move |n| (nfa, Node(n))))
+        {
+            let Some(transitions) = nfa.transitions(node) else {
+                continue;
+            };
+            bounds.extend(transitions.iter().map(|(lo, hi, _)| (lo, hi)));
+            for (look, _) in transitions.looks() {
+                match look {
+                    Look::WordBoundary | Look::NotWordBoundary => {
+                        bounds.extend_from_slice(word_class().ranges())
+                    }
+                    _ => bounds.push(('\n', '\n')),
+                }
+            }
+        }
+        Alphabet::new(bounds)
+    }
+
+    /// The coarsest partition in which each of `ranges` is a union of parts.
+    pub fn new<I: IntoIterator<Item = (char, char)>>(ranges: I) -> Alphabet {
+        let mut starts = vec!['\0'];
+        for (lo, hi) in ranges {
+            starts.push(lo);
+            starts.extend(succ(hi));
+        }
+        starts.sort();
+        starts.dedup();
+        let mut ascii = [0; 128];
+        let mut k = 0;
+        for (ch, class) in ascii.iter_mut().enumerate() {
+            while starts.get(k + 1).is_some_and(|&next| next as usize <= ch) {
+                k += 1;
+            }
+            *class = k;
+        }
+        Alphabet { starts, ascii }
+    }
+
+    /// The number of ranges.
+    pub fn len(&self) -> usize {
+        self.starts.len()
+    }
+
+    /// Always false, since the ranges cover every char.
+    pub fn is_empty(&self) -> bool {
+        self.starts.is_empty()
+    }
+
+    /// The index of the range holding `ch`.
+    pub fn class_of(&self, ch: char) -> usize {
+        if ch.is_ascii() {
+            return self.ascii[ch as usize];
+        }
+        match self.starts.binary_search(&ch) {
+            Ok(k) => k,
+            Err(k) => k - 1,
+        }
+    }
+
+    /// Some char in the `k`th range, standing in for all of them.
+    pub fn representative(&self, k: usize) -> char {
+        self.starts[k]
+    }
+
+    /// The `k`th range, as inclusive bounds.
+    pub fn range(&self, k: usize) -> (char, char) {
+        let hi = match self.starts.get(k + 1) {
+            Some(&next) => pred(next).unwrap(),
+            None => char::MAX,
+        };
+        (self.starts[k], hi)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::dfa::alphabet::*;
+
+    #[test]
+    pub fn test_new() {
+        let alphabet = Alphabet::new([('a', 'c'), ('b', 'd'), ('x', 'x')]);
+        assert_eq!(alphabet.len(), 7);
+        assert_eq!(alphabet.range(0), ('\0', '`'));
+        assert_eq!(alphabet.range(1), ('a', 'a'));
+        assert_eq!(alphabet.range(2), ('b', 'c'));
+        assert_eq!(alphabet.range(3), ('d', 'd'));
+        assert_eq!(alphabet.range(4), ('e', 'w'));
+        assert_eq!(alphabet.range(5), ('x', 'x'));
+        assert_eq!(alphabet.range(6), ('y', char::MAX));
+        assert_eq!(alphabet.representative(2), 'b');
+    }
+
+    #[test]
+    pub fn test_class_of() {
+        let alphabet = Alphabet::new([('a', 'c'), ('é', 'ü')]);
+        for ch in ['\0', 'a', 'c', 'd', 'é', 'ö', 'ü', 'ž', char::MAX] {
+            let (lo, hi) = alphabet.range(alphabet.class_of(ch));
+            assert!(lo <= ch && ch <= hi);
+        }
+        assert_eq!(alphabet.class_of('b'), alphabet.class_of('a'));
+        assert_ne!(alphabet.class_of('d'), alphabet.class_of('c'));
+    }
+
+    #[test]
+    pub fn test_of() {
+        let nfa = NFA::from_pattern("[a-f]x").unwrap();
+        assert_eq!(Alphabet::of(&nfa).len(), 5);
+        let nfa = NFA::from_pattern(r"a\b").unwrap();
+        assert!(Alphabet::of(&nfa).len() > 100);
+    }
+}
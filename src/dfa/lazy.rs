@@ -0,0 +1,254 @@
+use super::alphabet::Alphabet;
+use super::{accepts, start_subset, successor, Subset};
+use crate::nfa::look::Side;
+use crate::nfa::node::Node;
+use crate::nfa::NFA;
+use char_stream::CharStream;
+use std::collections::{HashMap, HashSet};
+use std::mem::size_of;
+
+/// The cache budget used by `LazyDFA::new`, in bytes.
+pub const DEFAULT_CACHE_CAPACITY: usize = 2 * 1024 * 1024;
+
+/// How many chars each cached state should serve, on average, between two
+/// flushes of the cache. A search that flushes sooner than that gains little
+/// over simulating the NFA directly.
+const MIN_CHARS_PER_STATE: usize = 10;
+
+/// How many wasteful flushes a search tolerates before giving up on the
+/// cache.
+const MAX_WASTEFUL_FLUSHES: usize = 3;
+
+/// Marks a transition that has not been determinized yet.
+const UNKNOWN: usize = usize::MAX;
+
+/// A DFA built one state at a time while matching, so only the subsets an
+/// input actually visits are ever determinized. The states are kept in a
+/// cache of bounded size, which is flushed when full. If a search keeps
+/// flushing the cache, it finishes by simulating the NFA instead.
+#[derive(Debug, Clone)]
+pub struct LazyDFA<'n> {
+    nfa: &'n NFA,
+    alphabet: Alphabet,
+    looks: bool,
+    ids: HashMap<Subset, usize>,
+    subsets: Vec<Subset>,
+    /// As in `DFA`, but with `UNKNOWN` for transitions not yet computed.
+    table: Vec<usize>,
+    accepting: Vec<bool>,
+    /// An estimate of the memory held by the cache, in bytes.
+    size: usize,
+    capacity: usize,
+    flushes: usize,
+    fallbacks: usize,
+}
+
+impl<'n> LazyDFA<'n> {
+    pub fn new(nfa: &'n NFA) -> LazyDFA<'n> {
+        LazyDFA::with_capacity(nfa, DEFAULT_CACHE_CAPACITY)
+    }
+
+    /// A lazy DFA whose cache holds about `capacity` bytes of states. The
+    /// cache always has room for at least two states, whatever `capacity`.
+    pub fn with_capacity(nfa: &'n NFA, capacity: usize) -> LazyDFA<'n> {
+        let mut dfa = LazyDFA {
+            nfa,
+            alphabet: Alphabet::of(nfa),
+            looks: nfa.uses_looks(),
+            ids: HashMap::new(),
+            subsets: vec![],
+            table: vec![],
+            accepting: vec![],
+            size: 0,
+            capacity,
+            flushes: 0,
+            fallbacks: 0,
+        };
+        dfa.add(start_subset(nfa));
+        dfa
+    }
+
+    /// The number of states currently cached.
+    pub fn states(&self) -> usize {
+        self.subsets.len()
+    }
+
+    /// How many times the cache has been flushed.
+    pub fn flushes(&self) -> usize {
+        self.flushes
+    }
+
+    /// How many searches gave up on the cache and fell back to the NFA.
+    pub fn fallbacks(&self) -> usize {
+        self.fallbacks
+    }
+
+    /// Roughly how many bytes caching `subset` takes: its row of the table,
+    /// and its nodes, which are held both in `subsets` and as a key of `ids`.
+    fn cost(&self, subset: &Subset) -> usize {
+        self.alphabet.len() * size_of::<usize>()
+            + 2 * subset.0.len() * size_of::<Node>()
+            + 2 * size_of::<Subset>()
+            + size_of::<bool>()
+    }
+
+    fn add(&mut self, subset: Subset) -> usize {
+        let id = self.subsets.len();
+        self.size += self.cost(&subset);
+        self.accepting.push(accepts(self.nfa, &subset));
+        self.table
+            .extend(std::iter::repeat_n(UNKNOWN, self.alphabet.len()));
+        self.ids.insert(subset.clone(), id);
+        self.subsets.push(subset);
+        id
+    }
+
+    /// Empties the cache.
+    fn flush(&mut self) {
+        self.flushes += 1;
+        self.ids.clear();
+        self.subsets.clear();
+        self.table.clear();
+        self.accepting.clear();
+        self.size = 0;
+    }
+
+    /// Whether the cache is too full to take `subset` as well.
+    fn is_full(&self, subset: &Subset) -> bool {
+        self.subsets.len() >= 2 && self.size + self.cost(subset) > self.capacity
+    }
+
+    pub fn is_match(&mut self, stream: &mut CharStream) -> bool {
+        let m = self.alphabet.len();
+        let start = start_subset(self.nfa);
+        let mut state = match self.ids.get(&start) {
+            Some(&id) => id,
+            None => {
+                if self.is_full(&start) {
+                    self.flush();
+                }
+                self.add(start)
+            }
+        };
+        // chars read since the cache was last flushed
+        let mut read = 0;
+        let mut wasteful = 0;
+        while let Some(ch) = stream.next() {
+            read += 1;
+            let k = self.alphabet.class_of(ch);
+            let next = self.table[state * m + k];
+            if next != UNKNOWN {
+                state = next;
+                continue;
+            }
+            let subset = successor(
+                self.nfa,
+                self.looks,
+                &self.subsets[state],
+                self.alphabet.representative(k),
+            );
+            if let Some(&id) = self.ids.get(&subset) {
+                self.table[state * m + k] = id;
+                state = id;
+            } else if self.is_full(&subset) {
+                if read < MIN_CHARS_PER_STATE * self.subsets.len() {
+                    wasteful += 1;
+                    if wasteful == MAX_WASTEFUL_FLUSHES {
+                        self.fallbacks += 1;
+                        return self.simulate(subset, stream);
+                    }
+                }
+                read = 0;
+                self.flush();
+                state = self.add(subset);
+            } else {
+                let id = self.add(subset);
+                self.table[state * m + k] = id;
+                state = id;
+            }
+        }
+        self.accepting[state]
+    }
+
+    /// Finishes a search by simulating the NFA from `subset`.
+    fn simulate(&self, (nodes, mut before): Subset, stream: &mut CharStream) -> bool {
+        let mut nodes: HashSet<Node> = nodes.into_iter().collect();
+        for ch in stream {
+            self.nfa.close(&mut nodes, before, Side::of(Some(ch)));
+            before = Side::of(Some(ch));
+            nodes = self.nfa.step(&nodes, ch);
+        }
+        accepts(self.nfa, &(nodes.into_iter().collect(), before))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::dfa::lazy::*;
+
+    /// A deterministic jumble of `a`s and `b`s.
+    fn jumble(len: usize) -> String {
+        let mut x: u32 = 12345;
+        (0..len)
+            .map(|_| {
+                x = x.wrapping_mul(1103515245).wrapping_add(12345);
+                if x >> 16 & 1 == 0 {
+                    'a'
+                } else {
+                    'b'
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    pub fn test_agrees_with_nfa() {
+        for pattern in ["(a|b)*abb", "a*b*", r"a\b.*", "^a$", "[^a]*é"] {
+            let nfa = NFA::from_pattern(pattern).unwrap();
+            let mut lazy = LazyDFA::new(&nfa);
+            for haystack in ["", "a", "abb", "babb", "ab", "a b", "aé", "bbé"] {
+                assert_eq!(
+                    lazy.is_match(&mut CharStream::from(haystack)),
+                    nfa.is_match(&mut CharStream::from(haystack))
+                );
+            }
+            assert_eq!(lazy.flushes(), 0);
+        }
+    }
+
+    #[test]
+    pub fn test_cache_is_reused() {
+        let nfa = NFA::from_pattern("(a|b)*abb").unwrap();
+        let mut lazy = LazyDFA::new(&nfa);
+        assert_eq!(lazy.states(), 1);
+        assert!(lazy.is_match(&mut CharStream::from("abababb")));
+        let states = lazy.states();
+        assert!(!lazy.is_match(&mut CharStream::from("ababab")));
+        assert_eq!(lazy.states(), states);
+    }
+
+    #[test]
+    pub fn test_bounded_cache() {
+        let nfa = NFA::from_pattern("(a|b)*a(a|b){20}").unwrap();
+        let haystack = jumble(5000);
+        let expected = nfa.is_match(&mut CharStream::from(haystack.as_str()));
+
+        let mut lazy = LazyDFA::with_capacity(&nfa, 64 * 1024);
+        assert_eq!(
+            lazy.is_match(&mut CharStream::from(haystack.as_str())),
+            expected
+        );
+        assert!(lazy.flushes() > 0);
+        assert_eq!(lazy.fallbacks(), 1);
+        assert!(lazy.states() * 5 * 8 < 64 * 1024);
+
+        let mut lazy = LazyDFA::with_capacity(&nfa, 0);
+        for len in [0, 21, 22, 100, 1000] {
+            let haystack = jumble(len);
+            assert_eq!(
+                lazy.is_match(&mut CharStream::from(haystack.as_str())),
+                nfa.is_match(&mut CharStream::from(haystack.as_str()))
+            );
+        }
+    }
+}
@@ -0,0 +1,6 @@
+pub mod ast;
+pub mod dfa;
+pub mod nfa;
+pub mod parse;
+pub mod pike;
+pub mod regex;
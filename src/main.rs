@@ -1,5 +1,3 @@
-mod nfa;
-
 fn main() {
     println!("Hello, world!");
 }
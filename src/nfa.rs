@@ -1,36 +1,230 @@
+pub mod analysis;
+pub mod class;
+pub mod compare;
+pub mod glushkov;
+pub mod look;
 pub mod node;
+pub mod search;
+pub mod thompson;
+pub mod transitions;
+use crate::dfa::DFA;
+use crate::parse::{ErrorKind, Options, ParseError, MAX_COMPLEMENT_STATES};
 use char_stream::CharStream;
+use class::Class;
+use look::{Look, Side};
 use node::Node;
 use std::collections::{HashMap, HashSet};
+use transitions::Transitions;
+
+/// Which combinators `Expr::to_nfa_with` builds an automaton from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Construction {
+    /// The combinators of this module, which rewire char edges so that the
+    /// automaton needs no epsilon edges.
+    #[default]
+    Rewiring,
+    /// The combinators of `thompson`, which join parts with epsilon edges.
+    Thompson,
+    /// The position automaton built by `glushkov::glushkov`, with one node
+    /// per position in the pattern. Intersections and complements have no
+    /// position automaton, so any part of the expression holding one is joined
+    /// by the `Rewiring` combinators instead.
+    Glushkov,
+}
 
 #[derive(Debug)]
 pub struct NFA {
     states: usize,
     starting: HashSet<Node>,
-    delta: HashMap<(Node, char), HashSet<Node>>,
+    delta: HashMap<Node, Transitions>,
     finished: HashSet<Node>,
 }
 
 impl NFA {
+    /// Compiles a pattern such as `(ab|c)*d` into an automaton.
+    pub fn from_pattern(pattern: &str) -> Result<NFA, ParseError> {
+        NFA::from_pattern_with(pattern, Options::default())
+    }
+
+    /// Compiles a pattern under `options`. Fails with `PatternTooLarge` if a
+    /// complement takes more than `MAX_COMPLEMENT_STATES` to determinize.
+    pub fn from_pattern_with(pattern: &str, options: Options) -> Result<NFA, ParseError> {
+        let (expr, complements) = crate::parse::parse_complements(pattern, options)?;
+        expr.try_to_nfa_with(Construction::default(), MAX_COMPLEMENT_STATES)
+            .map_err(|index| {
+                let span = complements[index].clone();
+                ParseError::new(ErrorKind::PatternTooLarge, span, pattern)
+            })
+    }
+
+    /// How many nodes the automaton has, numbered from `Node(0)`.
+    pub fn states(&self) -> usize {
+        self.states
+    }
+
+    pub fn starting(&self) -> &HashSet<Node> {
+        &self.starting
+    }
+
+    pub fn finished(&self) -> &HashSet<Node> {
+        &self.finished
+    }
+
+    /// The outgoing edges of `node`, if it has any.
+    pub fn transitions(&self, node: Node) -> Option<&Transitions> {
+        self.delta.get(&node)
+    }
+
+    /// Whether any edge is a zero-width assertion, making acceptance depend on
+    /// the chars around each position and not just on the chars read.
+    pub fn uses_looks(&self) -> bool {
+        self.delta
+            .values()
+            .any(|transitions| transitions.looks().next().is_some())
+    }
+
+    /// Adds to `nodes` everything reachable from them through epsilon edges
+    /// and through zero-width edges whose `Look` holds between chars of the
+    /// kinds `before` and `after`.
+    pub fn close(&self, nodes: &mut HashSet<Node>, before: Side, after: Side) {
+        let mut stack: Vec<Node> = nodes.iter().copied().collect();
+        while let Some(node) = stack.pop() {
+            let Some(transitions) = self.delta.get(&node) else {
+                continue;
+            };
+            for new_node in transitions.zero_width(before, after) {
+                if nodes.insert(new_node) {
+                    stack.push(new_node);
+                }
+            }
+        }
+    }
+
+    /// The nodes reached from `nodes` by reading `ch`.
+    pub fn step(&self, nodes: &HashSet<Node>, ch: char) -> HashSet<Node> {
+        let mut new_nodes: HashSet<Node> = HashSet::new();
+        for node in nodes.iter() {
+            if let Some(set) = self.delta.get(node).and_then(|t| t.get(ch)) {
+                for &new_node in set.iter() {
+                    new_nodes.insert(new_node);
+                }
+            }
+        }
+        new_nodes
+    }
+
     pub fn is_match(&self, stream: &mut CharStream) -> bool {
         let mut nodes: HashSet<Node> = self.starting.clone();
+        let mut before = Side::Edge;
         for ch in stream {
-            let mut new_nodes: HashSet<Node> = HashSet::new();
-            for &node in nodes.iter() {
-                if let Some(set) = self.delta.get(&(node, ch)) {
-                    for &new_node in set.iter() {
-                        new_nodes.insert(new_node);
+            self.close(&mut nodes, before, Side::of(Some(ch)));
+            before = Side::of(Some(ch));
+            nodes = self.step(&nodes, ch);
+        }
+        self.close(&mut nodes, before, Side::Edge);
+        nodes.iter().any(|node| self.finished.contains(node))
+    }
+
+    /// Every char that some edge reads.
+    pub fn chars(&self) -> Class {
+        Class::new(
+            self.delta
+                .values()
+                .flat_map(|transitions| transitions.iter().map(|(lo, hi, _)| (lo, hi))),
+        )
+    }
+
+    pub fn has_epsilons(&self) -> bool {
+        self.delta
+            .values()
+            .any(|transitions| !transitions.epsilon().is_empty())
+    }
+
+    /// An equivalent automaton without epsilon edges. Each node takes over
+    /// the other edges of every node in its epsilon closure, and is finished
+    /// if any of them is. Nodes that then become unreachable are dropped, and
+    /// the rest renumbered in the order they are reached.
+    pub fn remove_epsilons(&self) -> NFA {
+        let closure = |node: Node| {
+            let mut nodes: HashSet<Node> = [node].into();
+            let mut stack = vec![node];
+            while let Some(node) = stack.pop() {
+                let Some(transitions) = self.delta.get(&node) else {
+                    continue;
+                };
+                for &new_node in transitions.epsilon() {
+                    if nodes.insert(new_node) {
+                        stack.push(new_node);
                     }
                 }
             }
-            nodes = new_nodes;
+            nodes
+        };
+
+        let mut starting: Vec<Node> = self.starting.iter().copied().collect();
+        starting.sort();
+        let mut ids: HashMap<Node, Node> = HashMap::new();
+        let mut order: Vec<Node> = vec![];
+        for &node in starting.iter() {
+            ids.insert(node, Node(order.len()));
+            order.push(node);
+        }
+        let mut edges: Vec<Transitions> = vec![];
+        let mut finished = HashSet::new();
+        let mut next = 0;
+        while next < order.len() {
+            let node = order[next];
+            let mut transitions = Transitions::new();
+            for other in closure(node) {
+                if self.finished.contains(&other) {
+                    finished.insert(Node(next));
+                }
+                if let Some(other) = self.delta.get(&other) {
+                    transitions.union(other);
+                }
+            }
+            transitions.clear_epsilon();
+            // enqueue the targets in a fixed order, so that the numbering
+            // does not depend on the iteration order of the sets
+            let mut targets: Vec<Node> = transitions
+                .iter()
+                .flat_map(|(_, _, set)| set)
+                .chain(transitions.looks().flat_map(|(_, set)| set))
+                .copied()
+                .collect();
+            targets.sort();
+            for target in targets {
+                ids.entry(target).or_insert_with(|| {
+                    order.push(target);
+                    Node(order.len() - 1)
+                });
+            }
+            edges.push(transitions);
+            next += 1;
+        }
+
+        let delta = edges
+            .into_iter()
+            .enumerate()
+            .map(|(n, transitions)| {
+                (
+                    Node(n),
+                    transitions.map(|set| set.iter().map(|node| ids[node]).collect()),
+                )
+            })
+            .filter(|(_, transitions)| !transitions.is_empty())
+            .collect();
+        NFA {
+            states: order.len(),
+            starting: starting.iter().map(|node| ids[node]).collect(),
+            delta,
+            finished,
         }
-        nodes.iter().any(|node| self.finished.contains(node))
     }
 }
 
 pub fn plus(first: &NFA, second: &NFA) -> NFA {
-    let increase = |&node| {
+    let increase = |&node: &Node| {
         let Node(n) = node;
         Node(n + first.states)
     };
@@ -48,9 +242,9 @@ pub fn plus(first: &NFA, second: &NFA) -> NFA {
 
     let mut delta = first.delta.clone();
 
-    for (&(Node(n), ch), set) in second.delta.iter() {
-        let set = set.iter().map(increase).collect();
-        delta.insert((Node(n + first.states), ch), set);
+    for (&Node(n), transitions) in second.delta.iter() {
+        let transitions = transitions.map(|set| set.iter().map(increase).collect());
+        delta.insert(Node(n + first.states), transitions);
     }
 
     NFA {
@@ -71,81 +265,426 @@ pub fn times(first: &NFA, second: &NFA) -> NFA {
             });
         }
     }
-    let increase = |&node : &Node| -> Node {
+    let increase = |&node: &Node| -> Node {
         let Node(n) = node;
-        return Node(n + first.states);
+        Node(n + first.states)
     };
     // any nodes mapping to a first.finished state should map to second.starting states as well
-    let mut delta = first.delta.clone();
+    let mut delta = HashMap::new();
     let finished: HashSet<Node> = second.finished.clone().iter().map(increase).collect();
     let second_starting: HashSet<Node> = second.starting.clone().iter().map(increase).collect();
-    for (&(Node(n), ch), set) in first.delta.iter() {
-        let mut new_set: HashSet<Node> = set.clone();
-        let mut added_second_starting = false;
-        for &Node(m) in set.iter() {
-            if first.finished.contains(&Node(m)) {
-                if !added_second_starting {
-                    added_second_starting = true;
-                    for &Node(p) in second_starting.iter() {
-                        new_set.insert(Node(p));
-                    }
-                    new_set = tmp;
-                }
+    for (&node, transitions) in first.delta.iter() {
+        let transitions = transitions.map(|set| {
+            let mut new_set: HashSet<Node> = set.clone();
+            if !set.is_disjoint(&first.finished) {
+                new_set.extend(second_starting.iter().copied());
             }
+            new_set
+        });
+        delta.insert(node, transitions);
+    }
+
+    for (node, transitions) in second.delta.iter() {
+        let transitions = transitions.map(|set| set.iter().map(increase).collect());
+        delta.insert(increase(node), transitions);
+    }
+
+    NFA {
+        states,
+        starting,
+        delta,
+        finished,
+    }
+}
+
+/// Concatenates all of `parts` in one pass, so that building a long chain
+/// copies each part once rather than re-copying the chain built so far as
+/// repeated `times` calls would.
+pub fn concat_all(parts: &[&NFA]) -> NFA {
+    if parts.is_empty() {
+        return empty();
+    }
+    let mut offsets = Vec::with_capacity(parts.len());
+    let mut states = 0;
+    for part in parts.iter() {
+        offsets.push(states);
+        states += part.states;
+    }
+    let shift = |i: usize, &Node(n): &Node| Node(n + offsets[i]);
+    let nullable: Vec<bool> = parts
+        .iter()
+        .map(|part| !part.starting.is_disjoint(&part.finished))
+        .collect();
+
+    // entries[i] holds the nodes a string can continue in once it has been
+    // read by parts[..i], skipping over parts that accept the empty string
+    let mut entries: Vec<HashSet<Node>> = vec![HashSet::new(); parts.len() + 1];
+    for i in (0..parts.len()).rev() {
+        let mut entry: HashSet<Node> = parts[i]
+            .starting
+            .iter()
+            .map(|node| shift(i, node))
+            .collect();
+        if nullable[i] {
+            entry.extend(entries[i + 1].iter().copied());
         }
-        delta.insert((Node(n), ch), new_set);
+        entries[i] = entry;
     }
 
-    for (&(Node(n), ch), set) in second.delta.iter() {
-        let new_set: HashSet<Node> = set.iter().map(increase).collect();
-        delta.insert((increase(&Node(n)), ch), new_set);
+    let mut finished = HashSet::new();
+    let mut delta = HashMap::new();
+    for (i, part) in parts.iter().enumerate() {
+        if nullable[i + 1..].iter().all(|&nullable| nullable) {
+            finished.extend(part.finished.iter().map(|node| shift(i, node)));
+        }
+        for (node, transitions) in part.delta.iter() {
+            let transitions = transitions.map(|set| {
+                let mut new_set: HashSet<Node> = set.iter().map(|node| shift(i, node)).collect();
+                if !set.is_disjoint(&part.finished) {
+                    new_set.extend(entries[i + 1].iter().copied());
+                }
+                new_set
+            });
+            delta.insert(shift(i, node), transitions);
+        }
     }
 
     NFA {
         states,
+        starting: entries.swap_remove(0),
+        delta,
+        finished,
+    }
+}
+
+/// The nodes of `intersect`, numbered in the order they are first reached.
+#[derive(Default)]
+struct Pairs {
+    ids: HashMap<(Node, Node), Node>,
+    pairs: Vec<(Node, Node)>,
+}
+
+impl Pairs {
+    /// The nodes for every pair of a node in `left` and one in `right`, taken
+    /// in a fixed order so that the numbering does not depend on the
+    /// iteration order of the sets.
+    fn product(&mut self, left: &HashSet<Node>, right: &HashSet<Node>) -> HashSet<Node> {
+        let mut left: Vec<Node> = left.iter().copied().collect();
+        let mut right: Vec<Node> = right.iter().copied().collect();
+        left.sort();
+        right.sort();
+        let mut nodes = HashSet::new();
+        for &p in left.iter() {
+            for &q in right.iter() {
+                let node = *self.ids.entry((p, q)).or_insert_with(|| {
+                    self.pairs.push((p, q));
+                    Node(self.pairs.len() - 1)
+                });
+                nodes.insert(node);
+            }
+        }
+        nodes
+    }
+}
+
+/// Accepts the strings accepted by both automata, by running them side by
+/// side: each node is a pair of nodes, one from each, and only the pairs
+/// reachable from the starting pairs are built. A char edge moves both halves
+/// of a pair at once, while a zero-width edge moves just one.
+pub fn intersect(first: &NFA, second: &NFA) -> NFA {
+    let mut pairs = Pairs::default();
+    let starting = pairs.product(&first.starting, &second.starting);
+    let mut delta = HashMap::new();
+    let mut finished = HashSet::new();
+    let none = Transitions::new();
+    let mut next = 0;
+    while next < pairs.pairs.len() {
+        let (p, q) = pairs.pairs[next];
+        let node = Node(next);
+        next += 1;
+        if first.finished.contains(&p) && second.finished.contains(&q) {
+            finished.insert(node);
+        }
+        let left = first.delta.get(&p).unwrap_or(&none);
+        let right = second.delta.get(&q).unwrap_or(&none);
+        let mut transitions = Transitions::new();
+
+        // both range lists are sorted, so their overlaps can be found by
+        // walking them together
+        let mut ranges = left.iter().peekable();
+        let mut others = right.iter().peekable();
+        while let (Some(&(lo, hi, set)), Some(&(other_lo, other_hi, other_set))) =
+            (ranges.peek(), others.peek())
+        {
+            if lo.max(other_lo) <= hi.min(other_hi) {
+                let nodes = pairs.product(set, other_set);
+                transitions.insert(lo.max(other_lo), hi.min(other_hi), &nodes);
+            }
+            if hi < other_hi {
+                ranges.next();
+            } else {
+                others.next();
+            }
+        }
+
+        let here_p: HashSet<Node> = [p].into();
+        let here_q: HashSet<Node> = [q].into();
+        for (look, set) in left.looks() {
+            transitions.insert_look(look, &pairs.product(set, &here_q));
+        }
+        for (look, set) in right.looks() {
+            transitions.insert_look(look, &pairs.product(&here_p, set));
+        }
+        transitions.insert_epsilon(&pairs.product(left.epsilon(), &here_q));
+        transitions.insert_epsilon(&pairs.product(&here_p, right.epsilon()));
+
+        if !transitions.is_empty() {
+            delta.insert(node, transitions);
+        }
+    }
+
+    NFA {
+        states: pairs.pairs.len(),
         starting,
         delta,
         finished,
     }
 }
 
+/// Accepts every string that `nfa` rejects. Same as `complement_within` with
+/// every Unicode scalar value as the alphabet.
+pub fn complement(nfa: &NFA) -> NFA {
+    complement_within(nfa, &Class::full())
+}
+
+/// Accepts the strings of chars in `alphabet` that `nfa` rejects, such as
+/// those over `nfa.chars()` to stay within the chars the automaton mentions.
+///
+/// The automaton is determinized and minimized, and the accepting states
+/// flipped, so the result has no zero-width edges. Any assertions in `nfa` are
+/// decided as if each string were a whole haystack of its own.
+///
+/// Determinizing can take exponentially many states in the size of `nfa`, as
+/// for `(a|b)*a(a|b){n}`; `try_complement` gives up past a limit instead.
+pub fn complement_within(nfa: &NFA, alphabet: &Class) -> NFA {
+    flip(&DFA::from_nfa(nfa).minimize(), alphabet)
+}
+
+/// Same as `complement`, or `None` if determinizing `nfa` takes more than
+/// `max_states` states.
+pub fn try_complement(nfa: &NFA, max_states: usize) -> Option<NFA> {
+    let dfa = DFA::from_nfa_within(nfa, max_states)?;
+    Some(flip(&dfa.minimize(), &Class::full()))
+}
+
+/// The NFA with the states and transitions of `dfa` within `alphabet`, and
+/// its rejecting states as the finished ones.
+fn flip(dfa: &DFA, alphabet: &Class) -> NFA {
+    // the parts of the DFA's ranges that lie in the alphabet, each with the
+    // index of its range; both lists are sorted, so they can be walked
+    // together
+    let mut pieces: Vec<(char, char, usize)> = vec![];
+    let mut allowed = alphabet.ranges().iter().peekable();
+    let mut k = 0;
+    while let Some(&&(lo, hi)) = allowed.peek() {
+        if k == dfa.alphabet().len() {
+            break;
+        }
+        let (class_lo, class_hi) = dfa.alphabet().range(k);
+        if lo.max(class_lo) <= hi.min(class_hi) {
+            pieces.push((lo.max(class_lo), hi.min(class_hi), k));
+        }
+        if hi < class_hi {
+            allowed.next();
+        } else {
+            k += 1;
+        }
+    }
+
+    let mut delta = HashMap::new();
+    let mut finished = HashSet::new();
+    for state in 0..dfa.states() {
+        if !dfa.is_accepting(state) {
+            finished.insert(Node(state));
+        }
+        let transitions = Transitions::from_ranges(
+            pieces
+                .iter()
+                .map(|&(lo, hi, k)| (lo, hi, [Node(dfa.next_class(state, k))].into())),
+        );
+        if !transitions.is_empty() {
+            delta.insert(Node(state), transitions);
+        }
+    }
+
+    NFA {
+        states: dfa.states(),
+        starting: [Node(dfa.start())].into(),
+        delta,
+        finished,
+    }
+}
+
 pub fn unit(ch: char) -> NFA {
+    class(Class::single(ch))
+}
+
+/// Accepts any single char in `class`, using one range-labelled transition
+/// however many chars the class holds.
+pub fn class(class: Class) -> NFA {
     NFA {
         states: 2,
         starting: [Node(0)].into(),
-        delta: [((Node(0), ch), [Node(1)].into())].into(),
+        delta: [(Node(0), Transitions::from_class(&class, &[Node(1)].into()))].into(),
         finished: [Node(1)].into(),
     }
 }
 
+/// Accepts only the empty string, and only where `look` holds.
+pub fn look(look: Look) -> NFA {
+    NFA {
+        states: 2,
+        starting: [Node(0)].into(),
+        delta: [(Node(0), Transitions::from_look(look, &[Node(1)].into()))].into(),
+        finished: [Node(1)].into(),
+    }
+}
+
+/// Accepts any single char, except `\n` unless `dot_all` is set.
+pub fn any(dot_all: bool) -> NFA {
+    if dot_all {
+        class(Class::full())
+    } else {
+        class(Class::single('\n').negate())
+    }
+}
+
 pub fn star(nfa: &NFA) -> NFA {
+    // a fresh starting node, so that edges looping back to the old starting
+    // nodes do not make them accept a partial iteration
+    let start = Node(nfa.states);
     let mut finished = nfa.finished.clone();
+    let mut delta: HashMap<Node, Transitions> = HashMap::new();
+    for (&node, transitions) in nfa.delta.iter() {
+        let transitions = transitions.map(|set| {
+            let mut new_set = set.clone();
+            if !set.is_disjoint(&nfa.finished) {
+                new_set.extend(nfa.starting.iter().copied());
+            }
+            new_set
+        });
+        delta.insert(node, transitions);
+    }
+    let mut start_transitions = Transitions::new();
+    for node in nfa.starting.iter() {
+        if let Some(transitions) = delta.get(node) {
+            start_transitions.union(transitions);
+        }
+    }
+    delta.insert(start, start_transitions);
+    finished.insert(start);
+
+    NFA {
+        states: nfa.states + 1,
+        starting: [start].into(),
+        delta,
+        finished,
+    }
+}
+
+/// Accepts one or more strings of `nfa` in a row.
+pub fn one_or_more(nfa: &NFA) -> NFA {
+    repeat(nfa, 1, None)
+}
+
+/// Accepts the strings of `nfa` and the empty string.
+pub fn optional(nfa: &NFA) -> NFA {
+    plus(nfa, &empty())
+}
+
+/// Accepts the strings of `nfa` other than the empty string, through a fresh
+/// starting node that is not finished.
+fn without_empty(nfa: &NFA) -> NFA {
+    let start = Node(nfa.states);
+    let mut start_transitions = Transitions::new();
+    for node in nfa.starting.iter() {
+        if let Some(transitions) = nfa.delta.get(node) {
+            start_transitions.union(transitions);
+        }
+    }
     let mut delta = nfa.delta.clone();
-    for (&(Node(n), ch), set) in nfa.delta.iter() {
-        let mut new_set = set.clone();
-        let added_starting = false;
-        for &Node(m) in set.iter() {
-            if nfa.finished.contains(&Node(m)) && !added_starting {
-                added_starting = true;
-                for &Node(p) in nfa.starting.iter() {
-                    new_set.insert(Node(p));
+    if !start_transitions.is_empty() {
+        delta.insert(start, start_transitions);
+    }
+    NFA {
+        states: nfa.states + 1,
+        starting: [start].into(),
+        delta,
+        finished: nfa.finished.clone(),
+    }
+}
+
+/// Accepts up to `count` strings of `nfa` in a row, as the nested optionals
+/// `(x(x(x)?)?)?`: the edges that finish each copy also lead into the next
+/// one only, rather than into every later copy as a chain of `optional`s
+/// would. `nfa` must not accept the empty string, as a copy matching it could
+/// not be skipped over.
+fn optionals(nfa: &NFA, count: usize) -> NFA {
+    let shift = |i: usize, &Node(n): &Node| Node(n + i * nfa.states);
+    // a node of its own for stopping before the first copy
+    let stop = Node(count * nfa.states);
+    let mut starting: HashSet<Node> = [stop].into();
+    if count > 0 {
+        starting.extend(nfa.starting.iter().map(|node| shift(0, node)));
+    }
+    let mut finished: HashSet<Node> = [stop].into();
+    let mut delta = HashMap::new();
+    for i in 0..count {
+        finished.extend(nfa.finished.iter().map(|node| shift(i, node)));
+        let next: HashSet<Node> = if i + 1 < count {
+            nfa.starting.iter().map(|node| shift(i + 1, node)).collect()
+        } else {
+            HashSet::new()
+        };
+        for (node, transitions) in nfa.delta.iter() {
+            let transitions = transitions.map(|set| {
+                let mut new_set: HashSet<Node> = set.iter().map(|node| shift(i, node)).collect();
+                if !set.is_disjoint(&nfa.finished) {
+                    new_set.extend(next.iter().copied());
                 }
-            }
+                new_set
+            });
+            delta.insert(shift(i, node), transitions);
         }
-        delta.insert((Node(n), ch), new_set);
     }
-    nfa.starting.iter().for_each(|&Node(n)| {
-        finished.insert(Node(n));
-    });
 
     NFA {
-        states: nfa.states,
-        starting: nfa.starting.clone(),
+        states: count * nfa.states + 1,
+        starting,
         delta,
         finished,
     }
 }
 
+/// Accepts between `min` and `max` strings of `nfa` in a row, or at least
+/// `min` if `max` is `None`. The automaton has as many nodes and edges as
+/// the copies of `nfa` it is made of, plus a constant.
+pub fn repeat(nfa: &NFA, min: usize, max: Option<usize>) -> NFA {
+    // any copy of an operand that accepts the empty string may match it, so
+    // this is the same as up to `max` copies of the operand without the empty
+    // string, which chain into one another without skipping any
+    if !nfa.starting.is_disjoint(&nfa.finished) {
+        return repeat(&without_empty(nfa), 0, max);
+    }
+    let tail = match max {
+        None => star(nfa),
+        Some(max) => optionals(nfa, max.saturating_sub(min)),
+    };
+    let mut parts = vec![nfa; min];
+    parts.push(&tail);
+    concat_all(&parts)
+}
+
 pub fn empty() -> NFA {
     NFA {
         states: 1,
@@ -202,6 +741,270 @@ mod test {
         assert!(!nfa.is_match(&mut stream));
     }
 
+    #[test]
+    pub fn test_class() {
+        let nfa = class(Class::new([('a', 'z')]));
+        test_within_bounds(&nfa);
+        assert_eq!(nfa.delta.len(), 1);
+        let mut stream = CharStream::from_string(String::from("q"));
+        assert!(nfa.is_match(&mut stream));
+        stream = CharStream::from_string(String::from("Q"));
+        assert!(!nfa.is_match(&mut stream));
+        let nfa = class(Class::new([('a', 'z')]).negate());
+        assert_eq!(nfa.delta[&Node(0)].iter().count(), 2);
+        stream = CharStream::from_string(String::from("Q"));
+        assert!(nfa.is_match(&mut stream));
+    }
+
+    #[test]
+    pub fn test_any() {
+        let nfa = any(false);
+        test_within_bounds(&nfa);
+        let mut stream = CharStream::from_string(String::from("\u{10FFFF}"));
+        assert!(nfa.is_match(&mut stream));
+        stream = CharStream::from_string(String::from("\n"));
+        assert!(!nfa.is_match(&mut stream));
+        stream = CharStream::from_string(String::from("\n"));
+        assert!(any(true).is_match(&mut stream));
+    }
+
+    #[test]
+    pub fn test_concat_all() {
+        let nfa = concat_all(&[&unit('a'), &optional(&unit('b')), &star(&unit('c'))]);
+        test_within_bounds(&nfa);
+        for s in ["a", "ab", "ac", "abccc"] {
+            assert!(nfa.is_match(&mut CharStream::from(s)));
+        }
+        for s in ["", "b", "abb", "acb"] {
+            assert!(!nfa.is_match(&mut CharStream::from(s)));
+        }
+        let nfa = concat_all(&[&optional(&unit('a')), &optional(&unit('b'))]);
+        assert!(nfa.is_match(&mut CharStream::from("")));
+        assert!(nfa.is_match(&mut CharStream::from("b")));
+        assert!(concat_all(&[]).is_match(&mut CharStream::from("")));
+    }
+
+    #[test]
+    pub fn test_repeat() {
+        let nfa = repeat(&unit('a'), 2, Some(4));
+        test_within_bounds(&nfa);
+        // two copies, then two optional ones and the node for stopping early
+        assert_eq!(nfa.states, 2 * 2 + 2 * 2 + 1);
+        for (s, accepted) in [("a", false), ("aa", true), ("aaaa", true), ("aaaaa", false)] {
+            assert_eq!(nfa.is_match(&mut CharStream::from(s)), accepted);
+        }
+        let nfa = repeat(&unit('a'), 2, None);
+        test_within_bounds(&nfa);
+        for (s, accepted) in [("a", false), ("aa", true), ("aaaaaaa", true)] {
+            assert_eq!(nfa.is_match(&mut CharStream::from(s)), accepted);
+        }
+        let nfa = one_or_more(&times(&unit('a'), &unit('b')));
+        for (s, accepted) in [("", false), ("ab", true), ("abab", true), ("aba", false)] {
+            assert_eq!(nfa.is_match(&mut CharStream::from(s)), accepted);
+        }
+        assert!(repeat(&unit('a'), 0, Some(0)).is_match(&mut CharStream::from("")));
+    }
+
+    /// How many edges the automaton has, counting each target separately.
+    fn edges(nfa: &NFA) -> usize {
+        nfa.delta
+            .values()
+            .map(|transitions| {
+                transitions.epsilon().len()
+                    + transitions.looks().map(|(_, set)| set.len()).sum::<usize>()
+                    + transitions
+                        .iter()
+                        .map(|(_, _, set)| set.len())
+                        .sum::<usize>()
+            })
+            .sum()
+    }
+
+    #[test]
+    pub fn test_repeat_nullable() {
+        let nfa = repeat(&optional(&unit('a')), 2, Some(3));
+        for (s, accepted) in [("", true), ("a", true), ("aaa", true), ("aaaa", false)] {
+            assert_eq!(nfa.is_match(&mut CharStream::from(s)), accepted);
+        }
+        let nfa = repeat(&star(&unit('a')), 3, None);
+        assert!(nfa.is_match(&mut CharStream::from("")));
+        assert!(nfa.is_match(&mut CharStream::from("aaaaa")));
+    }
+
+    #[test]
+    pub fn test_repeat_linear_size() {
+        let nfa = repeat(&optional(&unit('a')), 0, Some(1000));
+        assert!(edges(&nfa) <= 3 * nfa.states);
+        let nfa = NFA::from_pattern("((a?){1000}){50}").unwrap();
+        // four nodes per `a?` without the empty string, plus a few per count
+        assert!(nfa.states <= 50 * (4 * 1000 + 2) + 1);
+        assert!(edges(&nfa) <= 3 * nfa.states);
+        assert!(nfa.is_match(&mut CharStream::from("aaa")));
+        assert!(!nfa.is_match(&mut CharStream::from("ab")));
+        // the copies of both sides only advance in step, so the product has
+        // a few pairs per length rather than one per pair of copies
+        let nfa = NFA::from_pattern(".{0,100}&.{0,100}").unwrap();
+        assert!(nfa.states <= 10 * 100);
+        assert!(nfa.is_match(&mut CharStream::from("a".repeat(100).as_str())));
+        assert!(!nfa.is_match(&mut CharStream::from("a".repeat(101).as_str())));
+    }
+
+    #[test]
+    pub fn test_look() {
+        let start = look(Look::Start);
+        test_within_bounds(&start);
+        assert!(start.is_match(&mut CharStream::from("")));
+        assert!(!start.is_match(&mut CharStream::from("a")));
+        let nfa = times(&times(&start, &unit('a')), &look(Look::End));
+        assert!(nfa.is_match(&mut CharStream::from("a")));
+        let nfa = star(&plus(&unit('a'), &look(Look::WordBoundary)));
+        assert!(nfa.is_match(&mut CharStream::from("aa")));
+        let nfa = concat_all(&[&unit('a'), &look(Look::WordBoundary), &unit(' ')]);
+        assert!(nfa.is_match(&mut CharStream::from("a ")));
+        let nfa = concat_all(&[&unit('a'), &look(Look::WordBoundary), &unit('b')]);
+        assert!(!nfa.is_match(&mut CharStream::from("ab")));
+    }
+
+    #[test]
+    pub fn test_remove_epsilons() {
+        let nfa = thompson::concat_all(&[
+            &thompson::star(&thompson::plus(&unit('a'), &look(Look::WordBoundary))),
+            &thompson::optional(&unit('b')),
+            &unit('c'),
+        ]);
+        assert!(nfa.has_epsilons());
+        let removed = nfa.remove_epsilons();
+        test_within_bounds(&removed);
+        assert!(!removed.has_epsilons());
+        assert!(removed.states < nfa.states);
+        for s in ["c", "ac", "bc", "aabc", "ab", "abbc", "cc", ""] {
+            assert_eq!(
+                removed.is_match(&mut CharStream::from(s)),
+                nfa.is_match(&mut CharStream::from(s)),
+                "{:?}",
+                s
+            );
+        }
+
+        let nfa = thompson::star(&thompson::star(&empty()));
+        let removed = nfa.remove_epsilons();
+        assert_eq!(removed.states, 1);
+        assert!(removed.is_match(&mut CharStream::from("")));
+        assert!(!removed.is_match(&mut CharStream::from("a")));
+    }
+
+    #[test]
+    pub fn test_remove_epsilons_without_epsilons() {
+        let nfa = times(&star(&plus(&unit('a'), &unit('b'))), &unit('c'));
+        let removed = nfa.remove_epsilons();
+        assert_eq!(removed.states, nfa.states);
+        for s in ["c", "abc", "ab", "cc"] {
+            assert_eq!(
+                removed.is_match(&mut CharStream::from(s)),
+                nfa.is_match(&mut CharStream::from(s))
+            );
+        }
+    }
+
+    #[test]
+    pub fn test_intersect() {
+        let nfa = intersect(
+            &star(&class(Class::new([('a', 'c')]))),
+            &times(&star(&any(false)), &unit('c')),
+        );
+        test_within_bounds(&nfa);
+        for (s, accepted) in [("c", true), ("abc", true), ("ab", false), ("adc", false)] {
+            assert_eq!(nfa.is_match(&mut CharStream::from(s)), accepted);
+        }
+        let nfa = intersect(&unit('a'), &unit('b'));
+        assert!(!nfa.is_match(&mut CharStream::from("a")));
+        assert!(nfa.finished.is_empty());
+
+        // each side keeps its own assertions and epsilon edges
+        let words = concat_all(&[&look(Look::WordBoundary), &star(&any(false))]);
+        let nfa = intersect(&words, &thompson::star(&unit('a')));
+        assert!(nfa.is_match(&mut CharStream::from("aa")));
+        assert!(!nfa.is_match(&mut CharStream::from("")));
+        let nfa = intersect(&words, &thompson::star(&unit(' ')));
+        assert!(!nfa.is_match(&mut CharStream::from(" ")));
+    }
+
+    #[test]
+    pub fn test_complement() {
+        let nfa = complement(&star(&unit('a')));
+        test_within_bounds(&nfa);
+        for (s, accepted) in [("", false), ("aaa", false), ("b", true), ("aé", true)] {
+            assert_eq!(nfa.is_match(&mut CharStream::from(s)), accepted);
+        }
+        let twice = complement(&nfa);
+        for (s, accepted) in [("", true), ("aaa", true), ("ab", false)] {
+            assert_eq!(twice.is_match(&mut CharStream::from(s)), accepted);
+        }
+        // the assertion only sees the string itself
+        let nfa = complement(&times(&look(Look::WordBoundary), &unit('a')));
+        assert!(!nfa.is_match(&mut CharStream::from("a")));
+        assert!(nfa.is_match(&mut CharStream::from("b")));
+    }
+
+    #[test]
+    pub fn test_complement_within() {
+        let nfa = plus(&unit('a'), &times(&unit('b'), &unit('c')));
+        assert_eq!(nfa.chars(), Class::new([('a', 'c')]));
+        let rest = complement_within(&nfa, &nfa.chars());
+        for (s, accepted) in [
+            ("", true),
+            ("a", false),
+            ("bc", false),
+            ("cb", true),
+            ("x", false),
+        ] {
+            assert_eq!(rest.is_match(&mut CharStream::from(s)), accepted);
+        }
+        let nothing = complement_within(&empty(), &Class::new([]));
+        assert!(!nothing.is_match(&mut CharStream::from("")));
+        assert!(!nothing.is_match(&mut CharStream::from("a")));
+    }
+
+    #[test]
+    pub fn test_try_complement() {
+        let nfa = NFA::from_pattern("(a|b)*a(a|b){3}").unwrap();
+        // remembering the last four chars takes at least 2^4 states
+        let states = DFA::from_nfa(&nfa).states();
+        assert!(states >= 16);
+        assert!(try_complement(&nfa, states - 1).is_none());
+        let rest = try_complement(&nfa, states).unwrap();
+        for (s, accepted) in [
+            ("abbb", false),
+            ("bbbb", true),
+            ("baaba", false),
+            ("abbbb", true),
+        ] {
+            assert_eq!(rest.is_match(&mut CharStream::from(s)), accepted);
+        }
+    }
+
+    #[test]
+    pub fn test_complement_state_limit() {
+        let error = |pattern: &str| {
+            let error = NFA::from_pattern(pattern).unwrap_err();
+            (error.kind(), error.span())
+        };
+        let blowup = "(a|b)*a(a|b){16}";
+        assert_eq!(
+            error(&format!("~({})", blowup)),
+            (ErrorKind::PatternTooLarge, 0..2)
+        );
+        assert_eq!(
+            error(&format!("a~(b)|~({})", blowup)),
+            (ErrorKind::PatternTooLarge, 6..8)
+        );
+        assert_eq!(
+            error(&format!("~(x~({}))", blowup)),
+            (ErrorKind::PatternTooLarge, 3..5)
+        );
+        assert!(NFA::from_pattern(&format!("~((a|b)*a(a|b){{8}})&{}", blowup)).is_ok());
+    }
+
     #[test]
     pub fn test_times() {
         let nfa = times(&unit('a'), &unit('b'));
@@ -0,0 +1,220 @@
+//! Questions about the language of an automaton as a whole.
+
+use super::compare::is_subset;
+use super::look::Side;
+use super::node::Node;
+use super::{any, star, NFA};
+use crate::dfa::alphabet::Alphabet;
+use crate::dfa::{accepts, successor, DFA};
+use std::collections::HashSet;
+
+/// How many chars there are from `lo` to `hi`, leaving out the surrogates.
+fn size((lo, hi): (char, char)) -> u128 {
+    let mut size = (hi as u32 - lo as u32 + 1) as u128;
+    if (lo as u32) < 0xD800 && (hi as u32) > 0xDFFF {
+        size -= 0x800;
+    }
+    size
+}
+
+/// The states of `dfa` from which an accepting state can be reached.
+fn live(dfa: &DFA) -> Vec<bool> {
+    let (n, m) = (dfa.states(), dfa.alphabet().len());
+    let mut sources: Vec<Vec<usize>> = vec![vec![]; n];
+    for state in 0..n {
+        for k in 0..m {
+            sources[dfa.next_class(state, k)].push(state);
+        }
+    }
+    let mut live: Vec<bool> = (0..n).map(|state| dfa.is_accepting(state)).collect();
+    let mut stack: Vec<usize> = (0..n).filter(|&state| live[state]).collect();
+    while let Some(state) = stack.pop() {
+        for &source in &sources[state] {
+            if !live[source] {
+                live[source] = true;
+                stack.push(source);
+            }
+        }
+    }
+    live
+}
+
+/// The live states reachable from the start of `dfa`, each after every live
+/// state it goes to, or `None` if they form a cycle, so that infinitely many
+/// strings are accepted.
+fn live_order(dfa: &DFA, live: &[bool]) -> Option<Vec<usize>> {
+    const UNSEEN: u8 = 0;
+    const OPEN: u8 = 1;
+    const DONE: u8 = 2;
+
+    let m = dfa.alphabet().len();
+    let mut marks = vec![UNSEEN; dfa.states()];
+    let mut order = vec![];
+    if !live[dfa.start()] {
+        return Some(order);
+    }
+    // depth first, with the next range to follow from each state on the stack
+    let mut stack = vec![(dfa.start(), 0)];
+    marks[dfa.start()] = OPEN;
+    while let Some(&mut (state, ref mut k)) = stack.last_mut() {
+        if *k == m {
+            marks[state] = DONE;
+            order.push(state);
+            stack.pop();
+            continue;
+        }
+        let next = dfa.next_class(state, *k);
+        *k += 1;
+        if !live[next] {
+            continue;
+        }
+        match marks[next] {
+            OPEN => return None,
+            UNSEEN => {
+                marks[next] = OPEN;
+                stack.push((next, 0));
+            }
+            _ => {}
+        }
+    }
+    Some(order)
+}
+
+impl NFA {
+    /// Whether no string at all is accepted.
+    ///
+    /// Searches the nodes reachable from the starting ones, each with the kind
+    /// of char just read so that zero-width edges are only followed where
+    /// their `Look` can hold, without determinizing.
+    pub fn is_empty(&self) -> bool {
+        let alphabet = Alphabet::of(self);
+        let looks = self.uses_looks();
+        let mut seen: HashSet<(Node, Side)> = HashSet::new();
+        let mut stack: Vec<(Node, Side)> = vec![];
+        for &node in &self.starting {
+            if seen.insert((node, Side::Edge)) {
+                stack.push((node, Side::Edge));
+            }
+        }
+        while let Some((node, side)) = stack.pop() {
+            let subset = (vec![node], side);
+            if accepts(self, &subset) {
+                return false;
+            }
+            for k in 0..alphabet.len() {
+                let (targets, side) = successor(self, looks, &subset, alphabet.representative(k));
+                for target in targets {
+                    if seen.insert((target, side)) {
+                        stack.push((target, side));
+                    }
+                }
+            }
+        }
+        true
+    }
+
+    /// Whether every string is accepted.
+    pub fn is_universal(&self) -> bool {
+        is_subset(&star(&any(true)), self).is_ok()
+    }
+
+    /// Whether only finitely many strings are accepted.
+    pub fn is_finite(&self) -> bool {
+        let dfa = DFA::from_nfa(self).minimize();
+        live_order(&dfa, &live(&dfa)).is_some()
+    }
+
+    /// How many distinct strings are accepted, or `None` if there are
+    /// infinitely many or too many to count in a `u128`.
+    ///
+    /// Strings are counted on the minimized DFA, where each has exactly one
+    /// path, so strings the automaton accepts along several paths are only
+    /// counted once.
+    pub fn cardinality(&self) -> Option<u128> {
+        let dfa = DFA::from_nfa(self).minimize();
+        let live = live(&dfa);
+        let alphabet = dfa.alphabet();
+        // counts[s] is how many strings are accepted from state `s`
+        let mut counts = vec![0u128; dfa.states()];
+        for state in live_order(&dfa, &live)? {
+            let mut count = dfa.is_accepting(state) as u128;
+            for k in 0..alphabet.len() {
+                let next = dfa.next_class(state, k);
+                if live[next] {
+                    count =
+                        count.checked_add(size(alphabet.range(k)).checked_mul(counts[next])?)?;
+                }
+            }
+            counts[state] = count;
+        }
+        Some(counts[dfa.start()])
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::nfa::analysis::*;
+
+    fn nfa(pattern: &str) -> NFA {
+        NFA::from_pattern(pattern).unwrap()
+    }
+
+    #[test]
+    pub fn test_size() {
+        assert_eq!(size(('a', 'z')), 26);
+        assert_eq!(size(('\0', char::MAX)), 0x110000 - 0x800);
+        assert_eq!(size(('\u{E000}', '\u{E000}')), 1);
+    }
+
+    #[test]
+    pub fn test_is_empty() {
+        for pattern in ["[]", "a&b", r"a\bb", "~((?s:.)*)", "a[]b|[]"] {
+            assert!(nfa(pattern).is_empty(), "{:?}", pattern);
+        }
+        for pattern in ["", "a", r"a\b ", "[]|x", "a*&b*"] {
+            assert!(!nfa(pattern).is_empty(), "{:?}", pattern);
+        }
+    }
+
+    #[test]
+    pub fn test_is_universal() {
+        for pattern in ["(?s:.*)", "(?s:.)*|a", "~([])", "~(a)|a"] {
+            assert!(nfa(pattern).is_universal(), "{:?}", pattern);
+        }
+        for pattern in [".*", "a*", "[]", "(?s:.+)"] {
+            assert!(!nfa(pattern).is_universal(), "{:?}", pattern);
+        }
+    }
+
+    #[test]
+    pub fn test_is_finite() {
+        for pattern in ["", "[]", "a|bc", "[]*", "a*&.{0,3}", r"a*\bb"] {
+            assert!(nfa(pattern).is_finite(), "{:?}", pattern);
+        }
+        for pattern in ["a*", "(a|b)+c", "~(a)"] {
+            assert!(!nfa(pattern).is_finite(), "{:?}", pattern);
+        }
+    }
+
+    #[test]
+    pub fn test_cardinality() {
+        for (pattern, count) in [
+            ("", 1),
+            ("[]", 0),
+            ("a|b", 2),
+            ("a|a", 1),
+            ("(a|ab)(c|bc)", 3),
+            ("[a-z]{2}", 676),
+            ("a*&.{0,3}", 4),
+            (r"\ba\b|b", 2),
+            ("(?s:.)", 0x110000 - 0x800),
+        ] {
+            assert_eq!(nfa(pattern).cardinality(), Some(count), "{:?}", pattern);
+        }
+        assert_eq!(nfa("a*").cardinality(), None);
+        // finite, but more than a u128 holds
+        let huge = nfa("(?s:.){7}");
+        assert!(huge.is_finite());
+        assert_eq!(huge.cardinality(), None);
+    }
+}
@@ -0,0 +1,132 @@
+/// A set of `char`s, stored as sorted, disjoint and non-adjacent inclusive
+/// ranges.
+#[derive(Hash, Eq, PartialEq, Debug, Clone)]
+pub struct Class {
+    ranges: Vec<(char, char)>,
+}
+
+/// The char after `ch`, skipping over the surrogate gap.
+pub fn succ(ch: char) -> Option<char> {
+    match ch {
+        '\u{D7FF}' => Some('\u{E000}'),
+        _ => char::from_u32(ch as u32 + 1),
+    }
+}
+
+/// The char before `ch`, skipping over the surrogate gap.
+pub fn pred(ch: char) -> Option<char> {
+    match ch {
+        '\u{E000}' => Some('\u{D7FF}'),
+        _ => char::from_u32((ch as u32).checked_sub(1)?),
+    }
+}
+
+impl Class {
+    /// Builds a class from arbitrary ranges, which may overlap or be given in
+    /// any order. Ranges with `lo > hi` are ignored.
+    pub fn new<I: IntoIterator<Item = (char, char)>>(ranges: I) -> Class {
+        let mut sorted: Vec<(char, char)> =
+            ranges.into_iter().filter(|&(lo, hi)| lo <= hi).collect();
+        sorted.sort();
+        let mut merged: Vec<(char, char)> = Vec::with_capacity(sorted.len());
+        for (lo, hi) in sorted {
+            match merged.last_mut() {
+                Some((_, last)) if succ(*last).is_none_or(|next| lo <= next) => {
+                    *last = (*last).max(hi);
+                }
+                _ => merged.push((lo, hi)),
+            }
+        }
+        Class { ranges: merged }
+    }
+
+    pub fn single(ch: char) -> Class {
+        Class {
+            ranges: vec![(ch, ch)],
+        }
+    }
+
+    /// Every Unicode scalar value.
+    pub fn full() -> Class {
+        Class {
+            ranges: vec![('\0', char::MAX)],
+        }
+    }
+
+    pub fn ranges(&self) -> &[(char, char)] {
+        &self.ranges
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+
+    pub fn contains(&self, ch: char) -> bool {
+        self.ranges
+            .binary_search_by(|&(lo, hi)| {
+                if hi < ch {
+                    std::cmp::Ordering::Less
+                } else if lo > ch {
+                    std::cmp::Ordering::Greater
+                } else {
+                    std::cmp::Ordering::Equal
+                }
+            })
+            .is_ok()
+    }
+
+    /// The chars not in this class, relative to every Unicode scalar value.
+    pub fn negate(&self) -> Class {
+        let mut ranges = vec![];
+        let mut lo = Some('\0');
+        for &(start, end) in self.ranges.iter() {
+            if let Some(lo) = lo {
+                if let Some(hi) = pred(start).filter(|&hi| lo <= hi) {
+                    ranges.push((lo, hi));
+                }
+            }
+            lo = succ(end);
+        }
+        if let Some(lo) = lo {
+            ranges.push((lo, char::MAX));
+        }
+        Class { ranges }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::nfa::class::*;
+
+    #[test]
+    pub fn test_normalizes() {
+        let class = Class::new([('x', 'z'), ('a', 'c'), ('b', 'f'), ('g', 'g'), ('q', 'p')]);
+        assert_eq!(class.ranges(), &[('a', 'g'), ('x', 'z')]);
+    }
+
+    #[test]
+    pub fn test_merges_across_surrogates() {
+        let class = Class::new([('\u{E000}', '\u{E001}'), ('a', '\u{D7FF}')]);
+        assert_eq!(class.ranges(), &[('a', '\u{E001}')]);
+    }
+
+    #[test]
+    pub fn test_contains() {
+        let class = Class::new([('a', 'z'), ('0', '9'), ('_', '_')]);
+        assert!(class.contains('a'));
+        assert!(class.contains('m'));
+        assert!(class.contains('_'));
+        assert!(class.contains('5'));
+        assert!(!class.contains('A'));
+        assert!(!class.contains('{'));
+    }
+
+    #[test]
+    pub fn test_negate() {
+        let class = Class::new([('\0', 'a'), ('c', 'c')]);
+        assert_eq!(class.negate().ranges(), &[('b', 'b'), ('d', char::MAX)]);
+        assert_eq!(class.negate().negate(), class);
+        assert!(Class::full().negate().is_empty());
+        assert_eq!(Class::new([]).negate(), Class::full());
+    }
+}
@@ -0,0 +1,370 @@
+//! Comparisons between the languages of two automata.
+
+use super::look::Side;
+use super::node::Node;
+use super::NFA;
+use crate::dfa::alphabet::Alphabet;
+use crate::dfa::{accepts, start_subset, successor, Subset};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::error::Error;
+use std::fmt;
+
+/// A string that one automaton accepts and the other rejects.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Counterexample {
+    pub string: String,
+    /// Whether it is the first automaton that accepts `string`.
+    pub in_first: bool,
+}
+
+impl fmt::Display for Counterexample {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (accepts, rejects) = if self.in_first {
+            ("first", "second")
+        } else {
+            ("second", "first")
+        };
+        write!(
+            f,
+            "{:?} is accepted by the {} automaton but not the {}",
+            self.string, accepts, rejects
+        )
+    }
+}
+
+impl Error for Counterexample {}
+
+/// A char of the `k`th range to show in a counterexample: the first printable
+/// ASCII char in it if there is one, as those are easier to read.
+fn example(alphabet: &Alphabet, k: usize) -> char {
+    let (lo, hi) = alphabet.range(k);
+    let printable = lo.max('!');
+    if printable <= hi.min('~') {
+        printable
+    } else {
+        lo
+    }
+}
+
+/// Numbers the subsets of one automaton as they are reached.
+struct Subsets<'n> {
+    nfa: &'n NFA,
+    looks: bool,
+    ids: HashMap<Subset, usize>,
+    subsets: Vec<Subset>,
+}
+
+impl<'n> Subsets<'n> {
+    fn new(nfa: &'n NFA) -> Subsets<'n> {
+        let mut subsets = Subsets {
+            nfa,
+            looks: nfa.uses_looks(),
+            ids: HashMap::new(),
+            subsets: vec![],
+        };
+        subsets.id(start_subset(nfa));
+        subsets
+    }
+
+    fn id(&mut self, subset: Subset) -> usize {
+        *self.ids.entry(subset).or_insert_with_key(|subset| {
+            self.subsets.push(subset.clone());
+            self.subsets.len() - 1
+        })
+    }
+
+    fn next(&mut self, id: usize, ch: char) -> usize {
+        let subset = successor(self.nfa, self.looks, &self.subsets[id], ch);
+        self.id(subset)
+    }
+
+    fn accepts(&self, id: usize) -> bool {
+        accepts(self.nfa, &self.subsets[id])
+    }
+}
+
+/// A union-find forest over the subsets of both automata, where the subset
+/// `id` of the first is element `2 * id` and that of the second `2 * id + 1`.
+#[derive(Default)]
+struct Classes {
+    parent: Vec<usize>,
+}
+
+impl Classes {
+    fn find(&mut self, element: usize) -> usize {
+        if element >= self.parent.len() {
+            self.parent.extend(self.parent.len()..=element);
+        }
+        let mut root = element;
+        while self.parent[root] != root {
+            root = self.parent[root];
+        }
+        // path compression
+        let mut element = element;
+        while self.parent[element] != root {
+            let next = self.parent[element];
+            self.parent[element] = root;
+            element = next;
+        }
+        root
+    }
+
+    /// Joins the classes of `a` and `b`, returning false if they were
+    /// already one.
+    fn union(&mut self, a: usize, b: usize) -> bool {
+        let (a, b) = (self.find(a), self.find(b));
+        self.parent[a] = b;
+        a != b
+    }
+}
+
+/// The index of a pair in `equivalent` or an element in `is_subset`, and a
+/// range read from it.
+type Step = (usize, usize);
+
+/// The string read to get to `pairs[index]`, following the steps back.
+fn path<T>(alphabet: &Alphabet, pairs: &[(T, Option<Step>)], index: usize) -> String {
+    let mut string = vec![];
+    let mut at = index;
+    while let Some((from, k)) = pairs[at].1 {
+        string.push(example(alphabet, k));
+        at = from;
+    }
+    string.into_iter().rev().collect()
+}
+
+/// Whether the two automata accept exactly the same strings, or else the
+/// shortest string on which they differ.
+///
+/// Both are determinized on the fly and the subsets they reach on the same
+/// input are merged with Hopcroft and Karp's union-find, so a pair of
+/// subsets is only ever explored if it is not already known to be
+/// equivalent to a pair explored before. Pairs are explored breadth first,
+/// which makes the first difference found the shortest.
+pub fn equivalent(first: &NFA, second: &NFA) -> Result<(), Counterexample> {
+    let alphabet = Alphabet::of_all(&[first, second]);
+    let mut lefts = Subsets::new(first);
+    let mut rights = Subsets::new(second);
+    let mut classes = Classes::default();
+    classes.union(0, 1);
+
+    // each pair to explore, with the pair it was reached from and the range
+    // read to get there
+    let mut pairs: Vec<((usize, usize), Option<Step>)> = vec![((0, 0), None)];
+    let mut queue = VecDeque::from([0]);
+    while let Some(index) = queue.pop_front() {
+        let ((left, right), _) = pairs[index];
+        let in_first = lefts.accepts(left);
+        if in_first != rights.accepts(right) {
+            return Err(Counterexample {
+                string: path(&alphabet, &pairs, index),
+                in_first,
+            });
+        }
+        for k in 0..alphabet.len() {
+            let ch = alphabet.representative(k);
+            let left = lefts.next(left, ch);
+            let right = rights.next(right, ch);
+            if classes.union(2 * left, 2 * right + 1) {
+                pairs.push(((left, right), Some((index, k))));
+                queue.push_back(pairs.len() - 1);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Whether `small` is a subset of `large`, both sorted.
+fn is_sorted_subset(small: &[Node], large: &[Node]) -> bool {
+    let mut large = large.iter();
+    small
+        .iter()
+        .all(|node| large.by_ref().any(|other| other == node))
+}
+
+/// A node of the first automaton in `is_subset`, with the kind of char just
+/// read, and the id of the subset the second is in.
+type Element = (Node, Side, usize);
+
+/// The subsets of the second automaton already paired with each node of the
+/// first in `is_subset`, keeping only the minimal ones.
+#[derive(Default)]
+struct Antichain {
+    minimal: HashMap<(Node, Side, Side), Vec<usize>>,
+}
+
+impl Antichain {
+    /// Adds the element unless a smaller subset is already paired with the
+    /// same node, dropping any larger ones. Returns whether it was added.
+    fn insert(&mut self, (node, side, id): Element, subsets: &Subsets) -> bool {
+        let (nodes, subset_side) = &subsets.subsets[id];
+        let minimal = self.minimal.entry((node, side, *subset_side)).or_default();
+        if minimal
+            .iter()
+            .any(|&other| is_sorted_subset(&subsets.subsets[other].0, nodes))
+        {
+            return false;
+        }
+        minimal.retain(|&other| !is_sorted_subset(nodes, &subsets.subsets[other].0));
+        minimal.push(id);
+        true
+    }
+}
+
+/// Whether every string accepted by `first` is also accepted by `second`, or
+/// else the shortest string that `first` accepts and `second` rejects.
+///
+/// Only `second` is determinized: `first` is followed one node at a time,
+/// each paired with the subset `second` is in after the same input. A pair is
+/// failing if its node accepts and its subset does not. Since a smaller subset
+/// accepts fewer strings, a pair whose subset contains that of another pair
+/// with the same node cannot fail any sooner, so only the pairs with minimal
+/// subsets, an antichain, are explored. This can avoid building most of the
+/// subsets that full determinization of `first` would need.
+pub fn is_subset(first: &NFA, second: &NFA) -> Result<(), Counterexample> {
+    let alphabet = Alphabet::of_all(&[first, second]);
+    let looks = first.uses_looks();
+    let mut rights = Subsets::new(second);
+    let mut antichain = Antichain::default();
+
+    let mut elements: Vec<(Element, Option<Step>)> = vec![];
+    let mut queue = VecDeque::new();
+    let mut starting: Vec<Node> = first.starting().iter().copied().collect();
+    starting.sort();
+    for node in starting {
+        if antichain.insert((node, Side::Edge, 0), &rights) {
+            elements.push(((node, Side::Edge, 0), None));
+            queue.push_back(elements.len() - 1);
+        }
+    }
+
+    while let Some(index) = queue.pop_front() {
+        let ((node, side, right), _) = elements[index];
+        let mut closed: HashSet<Node> = [node].into();
+        first.close(&mut closed, side, Side::Edge);
+        if !closed.is_disjoint(first.finished()) && !rights.accepts(right) {
+            return Err(Counterexample {
+                string: path(&alphabet, &elements, index),
+                in_first: true,
+            });
+        }
+        for k in 0..alphabet.len() {
+            let ch = alphabet.representative(k);
+            let mut closed: HashSet<Node> = [node].into();
+            first.close(&mut closed, side, Side::of(Some(ch)));
+            let mut targets: Vec<Node> = first.step(&closed, ch).into_iter().collect();
+            if targets.is_empty() {
+                continue;
+            }
+            targets.sort();
+            let right = rights.next(right, ch);
+            let side = if looks {
+                Side::of(Some(ch))
+            } else {
+                Side::Edge
+            };
+            for target in targets {
+                if antichain.insert((target, side, right), &rights) {
+                    elements.push(((target, side, right), Some((index, k))));
+                    queue.push_back(elements.len() - 1);
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::nfa::compare::*;
+    use char_stream::CharStream;
+
+    fn nfa(pattern: &str) -> NFA {
+        NFA::from_pattern(pattern).unwrap()
+    }
+
+    fn counterexample(first: &str, second: &str) -> Counterexample {
+        equivalent(&nfa(first), &nfa(second)).unwrap_err()
+    }
+
+    #[test]
+    pub fn test_equivalent() {
+        for (first, second) in [
+            ("(a|b)*", "(a*b*)*"),
+            ("(a|b)*abb", "(a*b*)*ab(b|bb(a|b)*abb)"),
+            ("a{2,}", "aa+"),
+            ("[a-c]", "a|b|c"),
+            ("[]", "a&b"),
+            ("~(a)", "(?s:|[^a]|..+)"),
+            (r"\ba\b", "a"),
+            ("^a|b$", "a|b"),
+        ] {
+            assert_eq!(equivalent(&nfa(first), &nfa(second)), Ok(()));
+        }
+    }
+
+    #[test]
+    pub fn test_counterexample() {
+        assert_eq!(
+            counterexample("(a|b)*abb", "(a|b)*bb"),
+            Counterexample {
+                string: String::from("bb"),
+                in_first: false,
+            }
+        );
+        assert_eq!(counterexample("a*", "a+").string, "");
+        assert_eq!(counterexample("a{0,3}", "a{0,4}").string, "aaaa");
+        let other = counterexample("[a-z]+", "[a-y]+");
+        assert_eq!(other.string, "z");
+        assert!(other.in_first);
+        assert_eq!(counterexample("(?s:.)", ".").string, "\n");
+        assert_eq!(
+            counterexample(r"a\b.", "a."),
+            Counterexample {
+                string: String::from("a0"),
+                in_first: false,
+            }
+        );
+    }
+
+    #[test]
+    pub fn test_is_subset() {
+        for (small, large) in [
+            ("abc", "a.*"),
+            ("[a-z_][a-z0-9_]*&.{0,16}", "[a-z0-9_]+"),
+            ("(ab)*", "(a|b)*"),
+            ("[]", "a"),
+            ("a", "a"),
+            (r"\ba", "a|b"),
+            ("(a|b)*a(a|b){12}", "(a|b)*"),
+        ] {
+            assert_eq!(is_subset(&nfa(small), &nfa(large)), Ok(()));
+        }
+        assert_eq!(
+            is_subset(&nfa("(a|b)*"), &nfa("(ab)*")),
+            Err(Counterexample {
+                string: String::from("a"),
+                in_first: true,
+            })
+        );
+        let (small, large) = (nfa("(a|b)*a(a|b){12}"), nfa("(a|b)*a(a|b){11}"));
+        let witness = is_subset(&small, &large).unwrap_err().string;
+        assert_eq!(witness.len(), 13);
+        assert!(small.is_match(&mut CharStream::from(witness.as_str())));
+        assert!(!large.is_match(&mut CharStream::from(witness.as_str())));
+        assert_eq!(is_subset(&nfa("x|a*"), &nfa("a+")).unwrap_err().string, "");
+        assert_eq!(
+            is_subset(&nfa("a.*"), &nfa("a[^b]*")).unwrap_err().string,
+            "ab"
+        );
+    }
+
+    #[test]
+    pub fn test_display() {
+        let counterexample = counterexample("ab", "a");
+        assert_eq!(
+            counterexample.to_string(),
+            "\"a\" is accepted by the second automaton but not the first"
+        );
+    }
+}
@@ -0,0 +1,288 @@
+//! Glushkov's construction, which builds the position automaton of an
+//! expression directly: one node for the start, plus one for each position,
+//! that is each occurrence of a literal, class, wildcard or assertion. Every
+//! edge into a position's node reads that position's char, or for an
+//! assertion is a zero-width edge for its `Look`, so there are no epsilon
+//! edges.
+
+use super::class::Class;
+use super::look::Look;
+use super::node::Node;
+use super::transitions::Transitions;
+use super::NFA;
+use crate::ast::Expr;
+use std::collections::{HashMap, HashSet};
+
+/// What an edge into a position's node is labelled with.
+enum Label {
+    Chars(Class),
+    Look(Look),
+}
+
+/// How the positions of a subexpression can begin and end a string of it.
+struct Linear {
+    /// Whether the subexpression accepts the empty string.
+    nullable: bool,
+    first: Vec<usize>,
+    last: Vec<usize>,
+}
+
+#[derive(Default)]
+struct Builder {
+    labels: Vec<Label>,
+    /// The positions that may come right after each position.
+    follow: Vec<Vec<usize>>,
+}
+
+impl Builder {
+    fn position(&mut self, label: Label) -> Linear {
+        self.labels.push(label);
+        self.follow.push(vec![]);
+        let position = self.labels.len() - 1;
+        Linear {
+            nullable: false,
+            first: vec![position],
+            last: vec![position],
+        }
+    }
+
+    fn concat(&mut self, left: Linear, right: Linear) -> Linear {
+        for &position in &left.last {
+            self.follow[position].extend(&right.first);
+        }
+        let mut first = left.first;
+        if left.nullable {
+            first.extend(&right.first);
+        }
+        let mut last = right.last;
+        if right.nullable {
+            last.extend(&left.last);
+        }
+        Linear {
+            nullable: left.nullable && right.nullable,
+            first,
+            last,
+        }
+    }
+
+    fn alt(left: Linear, right: Linear) -> Linear {
+        let mut first = left.first;
+        first.extend(right.first);
+        let mut last = left.last;
+        last.extend(right.last);
+        Linear {
+            nullable: left.nullable || right.nullable,
+            first,
+            last,
+        }
+    }
+
+    fn star(&mut self, inner: Linear) -> Linear {
+        for &position in &inner.last {
+            self.follow[position].extend(&inner.first);
+        }
+        Linear {
+            nullable: true,
+            ..inner
+        }
+    }
+
+    fn empty() -> Linear {
+        Linear {
+            nullable: true,
+            first: vec![],
+            last: vec![],
+        }
+    }
+
+    fn linearize(&mut self, expr: &Expr) -> Linear {
+        match expr {
+            Expr::Empty => Builder::empty(),
+            Expr::Literal(ch) => self.position(Label::Chars(Class::single(*ch))),
+            Expr::Class { negated, class } => self.position(Label::Chars(if *negated {
+                class.negate()
+            } else {
+                class.clone()
+            })),
+            Expr::Any { dot_all: true } => self.position(Label::Chars(Class::full())),
+            Expr::Any { dot_all: false } => {
+                self.position(Label::Chars(Class::single('\n').negate()))
+            }
+            Expr::Look(look) => self.position(Label::Look(*look)),
+            Expr::Concat(exprs) => exprs.iter().fold(Builder::empty(), |linear, expr| {
+                let next = self.linearize(expr);
+                self.concat(linear, next)
+            }),
+            Expr::Alt(exprs) => {
+                let mut linears = exprs.iter().map(|expr| self.linearize(expr));
+                let first = linears.next().unwrap_or_else(Builder::empty);
+                let linears: Vec<Linear> = linears.collect();
+                linears.into_iter().fold(first, Builder::alt)
+            }
+            Expr::Star(expr) => {
+                let inner = self.linearize(expr);
+                self.star(inner)
+            }
+            // each repetition is a fresh copy with positions of its own
+            Expr::Repeat { max: Some(0), .. } => Builder::empty(),
+            Expr::Repeat { expr, min, max } => {
+                let first = self.linearize(expr);
+                // as in `nfa::repeat`, copies of an operand that accepts the
+                // empty string may all match it, so this is the same as up to
+                // `max` copies of the operand without the empty string
+                let min = if first.nullable { 0 } else { *min };
+                let count = max.unwrap_or(min + 1);
+                let mut copies = vec![first];
+                while copies.len() < count {
+                    let next = self.linearize(expr);
+                    copies.push(next);
+                }
+                for copy in copies.iter_mut() {
+                    copy.nullable = false;
+                }
+                let optional = copies.split_off(min.min(count));
+                let tail = match max {
+                    None => {
+                        let inner = optional.into_iter().next().expect("a copy to star");
+                        self.star(inner)
+                    }
+                    // nested as (x(x(x)?)?)?, so that each copy is only
+                    // followed by the next
+                    Some(_) => optional
+                        .into_iter()
+                        .rev()
+                        .fold(Builder::empty(), |tail, copy| {
+                            let nested = self.concat(copy, tail);
+                            Builder::alt(nested, Builder::empty())
+                        }),
+                };
+                let linear = copies
+                    .into_iter()
+                    .fold(Builder::empty(), |linear, copy| self.concat(linear, copy));
+                self.concat(linear, tail)
+            }
+            Expr::Group { expr, .. } => self.linearize(expr),
+            Expr::Intersect(_) | Expr::Complement(_) => {
+                panic!("set operators have no position automaton")
+            }
+        }
+    }
+
+    /// Edges into each of `positions`, labelled by the positions.
+    fn edges(&self, positions: &[usize]) -> Transitions {
+        let mut transitions = Transitions::new();
+        for &position in positions {
+            let target: HashSet<Node> = [Node(position + 1)].into();
+            match &self.labels[position] {
+                Label::Chars(class) => transitions.union(&Transitions::from_class(class, &target)),
+                Label::Look(look) => transitions.insert_look(*look, &target),
+            }
+        }
+        transitions
+    }
+}
+
+/// The position automaton of `expr`, with the start at `Node(0)` and each
+/// position `p`, numbered from 0 in the order they appear in the pattern, at
+/// `Node(p + 1)`. Panics if `expr` holds an intersection or a complement.
+pub fn glushkov(expr: &Expr) -> NFA {
+    let mut builder = Builder::default();
+    let linear = builder.linearize(expr);
+
+    let mut delta = HashMap::new();
+    let start = builder.edges(&linear.first);
+    if !start.is_empty() {
+        delta.insert(Node(0), start);
+    }
+    for (position, follow) in builder.follow.iter().enumerate() {
+        let transitions = builder.edges(follow);
+        if !transitions.is_empty() {
+            delta.insert(Node(position + 1), transitions);
+        }
+    }
+    let mut finished: HashSet<Node> = linear.last.iter().map(|&p| Node(p + 1)).collect();
+    if linear.nullable {
+        finished.insert(Node(0));
+    }
+
+    NFA {
+        states: builder.labels.len() + 1,
+        starting: [Node(0)].into(),
+        delta,
+        finished,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::nfa::glushkov::*;
+    use crate::parse::parse;
+    use char_stream::CharStream;
+
+    #[test]
+    pub fn test_one_node_per_position() {
+        let nfa = glushkov(&parse("(a|b)*abb").unwrap());
+        assert_eq!(nfa.states, 6);
+        assert!(!nfa.has_epsilons());
+        // every edge reading `b` leads to the node of a `b` position
+        let targets: HashSet<Node> = nfa
+            .delta
+            .values()
+            .filter_map(|transitions| transitions.get('b'))
+            .flatten()
+            .copied()
+            .collect();
+        assert_eq!(targets, [Node(2), Node(4), Node(5)].into());
+        assert_eq!(glushkov(&parse("").unwrap()).states, 1);
+        assert_eq!(glushkov(&parse("a{2,3}").unwrap()).states, 4);
+    }
+
+    #[test]
+    pub fn test_counted_repetition_is_linear() {
+        for pattern in ["(a?){500}", "a{0,500}", "(ab?){1,500}c"] {
+            let nfa = glushkov(&parse(pattern).unwrap());
+            let edges: usize = nfa
+                .delta
+                .values()
+                .flat_map(|transitions| transitions.iter())
+                .map(|(_, _, set)| set.len())
+                .sum();
+            assert!(edges <= 3 * nfa.states, "{:?}", pattern);
+        }
+    }
+
+    #[test]
+    pub fn test_agrees_with_rewiring() {
+        for pattern in [
+            "(a|b)*abb",
+            "(ab|c)*d",
+            "a{2,3}b?",
+            "(a|)+",
+            "(a*b*)*",
+            r"^\bx|y$",
+            r"(a|\b)*b",
+            "[^a]*.",
+            "[]|c",
+            "a{0}",
+            "(a?){2,4}b",
+            "(a*b?){3}",
+            "(ab|c){1,3}",
+        ] {
+            let expr = parse(pattern).unwrap();
+            let rewired = expr.to_nfa();
+            let nfa = glushkov(&expr);
+            for haystack in [
+                "", "a", "ab", "abb", "aabb", "abcd", "d", "cd", "aa", "aab", "x", "y", "b", "c",
+                "\n",
+            ] {
+                assert_eq!(
+                    nfa.is_match(&mut CharStream::from(haystack)),
+                    rewired.is_match(&mut CharStream::from(haystack)),
+                    "{:?} on {:?}",
+                    pattern,
+                    haystack
+                );
+            }
+        }
+    }
+}
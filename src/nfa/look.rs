@@ -0,0 +1,120 @@
+use super::class::{succ, Class};
+use std::sync::OnceLock;
+
+/// A zero-width assertion about the chars on either side of a position.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Look {
+    /// `^`: the start of the text.
+    Start,
+    /// `$`: the end of the text.
+    End,
+    /// `^` in multi-line mode: the start of the text or just after a `\n`.
+    StartLine,
+    /// `$` in multi-line mode: the end of the text or just before a `\n`.
+    EndLine,
+    /// `\b`: a word char on exactly one side.
+    WordBoundary,
+    /// `\B`: word chars on both sides or on neither.
+    NotWordBoundary,
+}
+
+/// Everything a `Look` needs to know about the char on one side of a
+/// position.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Side {
+    /// There is no char: the position is at the start or end of the text.
+    Edge,
+    Newline,
+    Word,
+    Other,
+}
+
+impl Side {
+    pub fn of(ch: Option<char>) -> Side {
+        match ch {
+            None => Side::Edge,
+            Some('\n') => Side::Newline,
+            Some(ch) if is_word_char(ch) => Side::Word,
+            Some(_) => Side::Other,
+        }
+    }
+}
+
+/// Whether `ch` counts as part of a word for `\b` and `\B`.
+pub fn is_word_char(ch: char) -> bool {
+    ch.is_alphanumeric() || ch == '_'
+}
+
+/// Every word char, computed once on first use.
+pub fn word_class() -> &'static Class {
+    static WORD: OnceLock<Class> = OnceLock::new();
+    WORD.get_or_init(|| {
+        let mut ranges: Vec<(char, char)> = vec![];
+        for ch in ('\0'..=char::MAX).filter(|&ch| is_word_char(ch)) {
+            match ranges.last_mut() {
+                Some((_, hi)) if succ(*hi) == Some(ch) => *hi = ch,
+                _ => ranges.push((ch, ch)),
+            }
+        }
+        Class::new(ranges)
+    })
+}
+
+impl Look {
+    /// Whether the assertion holds at a position between `before` and
+    /// `after`, either of which is `None` at the edge of the text.
+    pub fn holds(self, before: Option<char>, after: Option<char>) -> bool {
+        self.holds_between(Side::of(before), Side::of(after))
+    }
+
+    /// Whether the assertion holds at a position between chars of the given
+    /// kinds.
+    pub fn holds_between(self, before: Side, after: Side) -> bool {
+        let word = |side: Side| side == Side::Word;
+        match self {
+            Look::Start => before == Side::Edge,
+            Look::End => after == Side::Edge,
+            Look::StartLine => matches!(before, Side::Edge | Side::Newline),
+            Look::EndLine => matches!(after, Side::Edge | Side::Newline),
+            Look::WordBoundary => word(before) != word(after),
+            Look::NotWordBoundary => word(before) == word(after),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::nfa::look::*;
+
+    #[test]
+    pub fn test_lines() {
+        assert!(Look::Start.holds(None, Some('a')));
+        assert!(!Look::Start.holds(Some('\n'), Some('a')));
+        assert!(Look::StartLine.holds(Some('\n'), Some('a')));
+        assert!(Look::End.holds(Some('a'), None));
+        assert!(!Look::End.holds(Some('a'), Some('\n')));
+        assert!(Look::EndLine.holds(Some('a'), Some('\n')));
+        assert!(!Look::EndLine.holds(Some('\n'), Some('a')));
+    }
+
+    #[test]
+    pub fn test_word_boundaries() {
+        assert!(Look::WordBoundary.holds(None, Some('a')));
+        assert!(Look::WordBoundary.holds(Some('é'), Some(' ')));
+        assert!(!Look::WordBoundary.holds(Some('_'), Some('9')));
+        assert!(!Look::WordBoundary.holds(None, None));
+        assert!(Look::NotWordBoundary.holds(Some(' '), Some('-')));
+        assert!(!Look::NotWordBoundary.holds(Some('x'), Some('-')));
+    }
+
+    #[test]
+    pub fn test_word_class() {
+        let word = word_class();
+        assert!(word.contains('a'));
+        assert!(word.contains('_'));
+        assert!(word.contains('ж'));
+        assert!(!word.contains(' '));
+        assert!(!word.contains('\n'));
+        assert!(word.ranges().len() > 100);
+    }
+}
@@ -0,0 +1,295 @@
+use super::look::Side;
+use super::node::Node;
+use super::NFA;
+use std::collections::{HashMap, HashSet};
+use std::ops::Range;
+
+/// A match found in a haystack, as byte offsets into it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Match {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Match {
+    pub fn range(&self) -> Range<usize> {
+        self.start..self.end
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+
+    pub fn len(&self) -> usize {
+        self.end - self.start
+    }
+
+    /// The matched text, given the haystack the match was found in.
+    pub fn as_str<'h>(&self, haystack: &'h str) -> &'h str {
+        &haystack[self.range()]
+    }
+}
+
+impl NFA {
+    /// Finds the leftmost match in `haystack`, extended as far as it goes.
+    pub fn find(&self, haystack: &str) -> Option<Match> {
+        self.find_at(haystack, 0)
+    }
+
+    /// Finds the leftmost match in `haystack` starting at or after the byte
+    /// offset `start`, extended as far as it goes. Panics if `start` is not on
+    /// a char boundary.
+    pub fn find_at(&self, haystack: &str, start: usize) -> Option<Match> {
+        // each live node, with the earliest position a match through it began
+        let mut threads: HashMap<Node, usize> = HashMap::new();
+        let mut found: Option<Match> = None;
+        let mut chars = haystack[start..].chars();
+        let mut pos = start;
+        let mut before = haystack[..start].chars().next_back();
+        loop {
+            if found.is_none() {
+                for &node in self.starting.iter() {
+                    threads.entry(node).or_insert(pos);
+                }
+            }
+            self.close_threads(&mut threads, before, chars.clone().next());
+            for (node, &from) in threads.iter() {
+                if self.finished.contains(node)
+                    && found.is_none_or(|m| from < m.start || from == m.start && pos > m.end)
+                {
+                    found = Some(Match {
+                        start: from,
+                        end: pos,
+                    });
+                }
+            }
+            // once something has matched, only threads that began no later
+            // can still produce a better match
+            if let Some(m) = found {
+                threads.retain(|_, from| *from <= m.start);
+                if threads.is_empty() {
+                    break;
+                }
+            }
+            let Some(ch) = chars.next() else {
+                break;
+            };
+            let mut new_threads: HashMap<Node, usize> = HashMap::new();
+            for (node, &from) in threads.iter() {
+                if let Some(set) = self.delta.get(node).and_then(|t| t.get(ch)) {
+                    for &new_node in set.iter() {
+                        let earliest = new_threads.entry(new_node).or_insert(from);
+                        *earliest = (*earliest).min(from);
+                    }
+                }
+            }
+            threads = new_threads;
+            before = Some(ch);
+            pos += ch.len_utf8();
+        }
+        found
+    }
+
+    /// Like `close`, but carrying each thread's starting position along to
+    /// the nodes it reaches, keeping the earliest.
+    fn close_threads(
+        &self,
+        threads: &mut HashMap<Node, usize>,
+        before: Option<char>,
+        after: Option<char>,
+    ) {
+        let mut stack: Vec<(Node, usize)> =
+            threads.iter().map(|(&node, &from)| (node, from)).collect();
+        while let Some((node, from)) = stack.pop() {
+            let Some(transitions) = self.delta.get(&node) else {
+                continue;
+            };
+            for new_node in transitions.zero_width(Side::of(before), Side::of(after)) {
+                let earliest = threads.entry(new_node).or_insert(usize::MAX);
+                if from < *earliest {
+                    *earliest = from;
+                    stack.push((new_node, from));
+                }
+            }
+        }
+    }
+
+    /// Iterates over the successive non-overlapping matches in `haystack`,
+    /// each found as by `find_at` from the end of the last.
+    ///
+    /// After an empty match the search moves on by one char, and an empty
+    /// match right where the previous match ended is skipped, so `a*` finds
+    /// `""`, `"aaa"` and nothing more in `"baaa"`.
+    pub fn find_iter<'n, 'h>(&'n self, haystack: &'h str) -> Matches<'n, 'h> {
+        Matches {
+            nfa: self,
+            haystack,
+            cursor: Cursor::default(),
+        }
+    }
+
+    /// Whether any substring of `haystack` is accepted.
+    pub fn is_match_anywhere(&self, haystack: &str) -> bool {
+        let mut nodes: HashSet<Node> = HashSet::new();
+        let mut chars = haystack.chars();
+        let mut before = None;
+        loop {
+            nodes.extend(self.starting.iter().copied());
+            self.close(&mut nodes, Side::of(before), Side::of(chars.clone().next()));
+            if !nodes.is_disjoint(&self.finished) {
+                return true;
+            }
+            let Some(ch) = chars.next() else {
+                return false;
+            };
+            nodes = self.step(&nodes, ch);
+            before = Some(ch);
+        }
+    }
+}
+
+/// How iterators over successive non-overlapping matches move through a
+/// haystack, shared so that they all skip empty matches the same way.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Cursor {
+    /// Where the next search starts, or past the end once there is nothing
+    /// left to search.
+    pos: usize,
+    last_end: Option<usize>,
+}
+
+impl Cursor {
+    /// Runs `find_at` from where the last match left off, returning what it
+    /// found along with the match. An empty match right where the previous
+    /// match ended is skipped by searching again one char later.
+    pub(crate) fn next<T, F>(&mut self, haystack: &str, mut find_at: F) -> Option<T>
+    where
+        F: FnMut(usize) -> Option<(Match, T)>,
+    {
+        if self.pos > haystack.len() {
+            return None;
+        }
+        let (mut m, mut found) = find_at(self.pos)?;
+        if m.is_empty() && self.last_end == Some(m.end) {
+            self.pos = after_char(haystack, m.end);
+            if self.pos > haystack.len() {
+                return None;
+            }
+            (m, found) = find_at(self.pos)?;
+        }
+        self.pos = if m.is_empty() {
+            after_char(haystack, m.end)
+        } else {
+            m.end
+        };
+        self.last_end = Some(m.end);
+        Some(found)
+    }
+}
+
+/// The byte offset just after the char at `pos`, or past the end of the
+/// haystack if there is none.
+fn after_char(haystack: &str, pos: usize) -> usize {
+    haystack[pos..]
+        .chars()
+        .next()
+        .map_or(haystack.len() + 1, |ch| pos + ch.len_utf8())
+}
+
+/// The iterator returned by `NFA::find_iter`.
+#[derive(Debug)]
+pub struct Matches<'n, 'h> {
+    nfa: &'n NFA,
+    haystack: &'h str,
+    cursor: Cursor,
+}
+
+impl<'n, 'h> Iterator for Matches<'n, 'h> {
+    type Item = Match;
+
+    fn next(&mut self) -> Option<Match> {
+        self.cursor.next(self.haystack, |pos| {
+            let m = self.nfa.find_at(self.haystack, pos)?;
+            Some((m, m))
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::nfa::search::*;
+
+    fn find(pattern: &str, haystack: &str) -> Option<(usize, usize)> {
+        let nfa = NFA::from_pattern(pattern).unwrap();
+        nfa.find(haystack).map(|m| (m.start, m.end))
+    }
+
+    #[test]
+    pub fn test_find() {
+        assert_eq!(find("b+", "aabbbc"), Some((2, 5)));
+        assert_eq!(find("x", "aabbbc"), None);
+        assert_eq!(find("timeout", "ERROR: timeout"), Some((7, 14)));
+    }
+
+    #[test]
+    pub fn test_find_leftmost_longest() {
+        assert_eq!(find("a|ab", "xab"), Some((1, 3)));
+        assert_eq!(find("bc|abcd", "abcd"), Some((0, 4)));
+        assert_eq!(find("abcd|bc", "abce"), Some((1, 3)));
+    }
+
+    #[test]
+    pub fn test_find_empty() {
+        assert_eq!(find("a*", "bab"), Some((0, 0)));
+        assert_eq!(find("", ""), Some((0, 0)));
+    }
+
+    #[test]
+    pub fn test_find_offsets_are_bytes() {
+        let haystack = "héllo wörld";
+        let nfa = NFA::from_pattern("w.r").unwrap();
+        let m = nfa.find(haystack).unwrap();
+        assert_eq!(m.range(), 7..11);
+        assert_eq!(m.as_str(haystack), "wör");
+    }
+
+    #[test]
+    pub fn test_find_at() {
+        let nfa = NFA::from_pattern("[0-9]+").unwrap();
+        assert_eq!(nfa.find_at("12 345", 0), Some(Match { start: 0, end: 2 }));
+        assert_eq!(nfa.find_at("12 345", 1), Some(Match { start: 1, end: 2 }));
+        assert_eq!(nfa.find_at("12 345", 2), Some(Match { start: 3, end: 6 }));
+        assert_eq!(nfa.find_at("12 345", 6), None);
+    }
+
+    fn find_all(pattern: &str, haystack: &str) -> Vec<(usize, usize)> {
+        let nfa = NFA::from_pattern(pattern).unwrap();
+        nfa.find_iter(haystack).map(|m| (m.start, m.end)).collect()
+    }
+
+    #[test]
+    pub fn test_find_iter() {
+        assert_eq!(
+            find_all("[0-9]+", "a1 22 333"),
+            vec![(1, 2), (3, 5), (6, 9)]
+        );
+        assert_eq!(find_all("aa", "aaaaa"), vec![(0, 2), (2, 4)]);
+        assert_eq!(find_all("x", "aaa"), vec![]);
+    }
+
+    #[test]
+    pub fn test_find_iter_empty_matches() {
+        assert_eq!(find_all("a*", "baaa"), vec![(0, 0), (1, 4)]);
+        assert_eq!(find_all("a*", "bab"), vec![(0, 0), (1, 2), (3, 3)]);
+        assert_eq!(find_all("", "é"), vec![(0, 0), (2, 2)]);
+        assert_eq!(find_all("", ""), vec![(0, 0)]);
+    }
+
+    #[test]
+    pub fn test_is_match_anywhere() {
+        let nfa = NFA::from_pattern("ERROR.*timeout").unwrap();
+        assert!(nfa.is_match_anywhere("12:00 ERROR: read timeout (5s)"));
+        assert!(!nfa.is_match_anywhere("12:00 WARN: read timeout"));
+        assert!(!nfa.is_match_anywhere("ERROR\ntimeout"));
+    }
+}
@@ -0,0 +1,243 @@
+//! Thompson's construction: combinators that join automata with epsilon
+//! edges instead of rewiring their char edges. Given automata with a single
+//! starting and a single finished node, such as those of `unit`, `class`,
+//! `look` and `empty` or the ones built here, each combinator adds a constant
+//! number of nodes and edges, so the automaton for an expression is linear in
+//! its size.
+
+use super::node::Node;
+use super::transitions::Transitions;
+use super::{empty, NFA};
+use std::collections::{HashMap, HashSet};
+
+/// The nodes and edges of all of `parts` side by side, each shifted past the
+/// ones before it, with room for `extra` new nodes at the end. Also returns
+/// where each part starts.
+fn embed(parts: &[&NFA], extra: usize) -> (NFA, Vec<usize>) {
+    let mut offsets = Vec::with_capacity(parts.len());
+    let mut states = 0;
+    let mut delta = HashMap::new();
+    for part in parts {
+        offsets.push(states);
+        let offset = states;
+        for (&Node(n), transitions) in part.delta.iter() {
+            let transitions =
+                transitions.map(|set| set.iter().map(|&Node(m)| Node(m + offset)).collect());
+            delta.insert(Node(n + offset), transitions);
+        }
+        states += part.states;
+    }
+    let nfa = NFA {
+        states: states + extra,
+        starting: HashSet::new(),
+        delta,
+        finished: HashSet::new(),
+    };
+    (nfa, offsets)
+}
+
+fn shifted(nodes: &HashSet<Node>, offset: usize) -> HashSet<Node> {
+    nodes.iter().map(|&Node(n)| Node(n + offset)).collect()
+}
+
+impl NFA {
+    /// Adds epsilon edges from each of `from` to every node of `to`.
+    fn link(&mut self, from: &HashSet<Node>, to: &HashSet<Node>) {
+        for &node in from {
+            self.delta
+                .entry(node)
+                .or_default()
+                .union(&Transitions::from_epsilon(to));
+        }
+    }
+}
+
+/// Accepts the strings of either automaton, through a new starting node with
+/// epsilon edges into both and a new finished node with epsilon edges out of
+/// both.
+pub fn plus(first: &NFA, second: &NFA) -> NFA {
+    let (mut nfa, offsets) = embed(&[first, second], 2);
+    let start: HashSet<Node> = [Node(nfa.states - 2)].into();
+    let end: HashSet<Node> = [Node(nfa.states - 1)].into();
+    for (part, offset) in [first, second].into_iter().zip(offsets) {
+        nfa.link(&start, &shifted(&part.starting, offset));
+        nfa.link(&shifted(&part.finished, offset), &end);
+    }
+    nfa.starting = start;
+    nfa.finished = end;
+    nfa
+}
+
+pub fn times(first: &NFA, second: &NFA) -> NFA {
+    concat_all(&[first, second])
+}
+
+/// Concatenates all of `parts` by an epsilon edge from the finished nodes of
+/// each to the starting nodes of the next.
+pub fn concat_all(parts: &[&NFA]) -> NFA {
+    let Some(last) = parts.len().checked_sub(1) else {
+        return empty();
+    };
+    let (mut nfa, offsets) = embed(parts, 0);
+    for i in 0..last {
+        let from = shifted(&parts[i].finished, offsets[i]);
+        let to = shifted(&parts[i + 1].starting, offsets[i + 1]);
+        nfa.link(&from, &to);
+    }
+    nfa.starting = shifted(&parts[0].starting, offsets[0]);
+    nfa.finished = shifted(&parts[last].finished, offsets[last]);
+    nfa
+}
+
+/// Loops back from the finished nodes of `nfa` to its starting nodes, between
+/// a new starting and a new finished node. If `skip`, the new starting node
+/// also has an epsilon edge straight to the new finished node.
+fn cycle(nfa: &NFA, skip: bool) -> NFA {
+    let (mut looped, _) = embed(&[nfa], 2);
+    let start: HashSet<Node> = [Node(nfa.states)].into();
+    let end: HashSet<Node> = [Node(nfa.states + 1)].into();
+    looped.link(&start, &nfa.starting);
+    looped.link(&nfa.finished, &nfa.starting);
+    looped.link(&nfa.finished, &end);
+    if skip {
+        looped.link(&start, &end);
+    }
+    looped.starting = start;
+    looped.finished = end;
+    looped
+}
+
+pub fn star(nfa: &NFA) -> NFA {
+    cycle(nfa, true)
+}
+
+/// Accepts one or more strings of `nfa` in a row.
+pub fn one_or_more(nfa: &NFA) -> NFA {
+    cycle(nfa, false)
+}
+
+/// Accepts the strings of `nfa` and the empty string.
+pub fn optional(nfa: &NFA) -> NFA {
+    plus(nfa, &empty())
+}
+
+/// Accepts up to `count` strings of `nfa` in a row, as the nested optionals
+/// `(x(x(x)?)?)?`. Each copy is entered through a new node with an epsilon
+/// edge straight to the one new finished node, and finishes into the node
+/// entering the next copy, so no node has more than two epsilon edges out.
+fn optionals(nfa: &NFA, count: usize) -> NFA {
+    let (mut nested, offsets) = embed(&vec![nfa; count], count + 1);
+    let first = count * nfa.states;
+    let entry = |i: usize| -> HashSet<Node> { [Node(first + i)].into() };
+    let end = entry(count);
+    for (i, &offset) in offsets.iter().enumerate() {
+        nested.link(&entry(i), &shifted(&nfa.starting, offset));
+        nested.link(&entry(i), &end);
+        nested.link(&shifted(&nfa.finished, offset), &entry(i + 1));
+    }
+    nested.starting = entry(0);
+    nested.finished = end;
+    nested
+}
+
+/// Accepts between `min` and `max` strings of `nfa` in a row, or at least
+/// `min` if `max` is `None`.
+pub fn repeat(nfa: &NFA, min: usize, max: Option<usize>) -> NFA {
+    let tail = match max {
+        None => star(nfa),
+        Some(max) => optionals(nfa, max.saturating_sub(min)),
+    };
+    let mut parts = vec![nfa; min];
+    parts.push(&tail);
+    concat_all(&parts)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::nfa::look::Look;
+    use crate::nfa::thompson::*;
+    use crate::nfa::{look, unit};
+    use char_stream::CharStream;
+
+    fn accepts(nfa: &NFA, haystack: &str) -> bool {
+        nfa.is_match(&mut CharStream::from(haystack))
+    }
+
+    #[test]
+    pub fn test_combinators() {
+        let nfa = times(&star(&plus(&unit('a'), &unit('b'))), &unit('c'));
+        for (s, accepted) in [("c", true), ("abbac", true), ("ab", false), ("cc", false)] {
+            assert_eq!(accepts(&nfa, s), accepted);
+        }
+        let nfa = one_or_more(&times(&unit('a'), &unit('b')));
+        for (s, accepted) in [("", false), ("ab", true), ("abab", true), ("aba", false)] {
+            assert_eq!(accepts(&nfa, s), accepted);
+        }
+        let nfa = repeat(&unit('a'), 2, Some(3));
+        for (s, accepted) in [("a", false), ("aa", true), ("aaa", true), ("aaaa", false)] {
+            assert_eq!(accepts(&nfa, s), accepted);
+        }
+        assert!(accepts(&concat_all(&[]), ""));
+        assert!(accepts(&optional(&unit('a')), ""));
+    }
+
+    #[test]
+    pub fn test_mixed_with_rewiring() {
+        let nfa = crate::nfa::times(&star(&unit('a')), &unit('b'));
+        for (s, accepted) in [("b", true), ("aab", true), ("a", false), ("ba", false)] {
+            assert_eq!(accepts(&nfa, s), accepted);
+        }
+        let nfa = crate::nfa::star(&concat_all(&[&unit('a'), &optional(&unit('b'))]));
+        for (s, accepted) in [("", true), ("aaba", true), ("ab", true), ("b", false)] {
+            assert_eq!(accepts(&nfa, s), accepted);
+        }
+    }
+
+    #[test]
+    pub fn test_single_ends() {
+        let nfa = star(&plus(&times(&unit('a'), &unit('b')), &optional(&unit('c'))));
+        assert_eq!(nfa.starting.len(), 1);
+        assert_eq!(nfa.finished.len(), 1);
+        // two nodes each for the star, the choice and the optional, plus the
+        // parts: `a` and `b` of two nodes each, `c` of two and `empty` of one
+        assert_eq!(nfa.states, 2 + 2 + 2 * 2 + (2 + 2 + 1));
+    }
+
+    fn epsilon_edges(nfa: &NFA) -> usize {
+        nfa.delta
+            .values()
+            .map(|transitions| transitions.epsilon().len())
+            .sum()
+    }
+
+    #[test]
+    pub fn test_linear_size() {
+        let nfa = repeat(&plus(&unit('a'), &unit('b')), 100, None);
+        let edges: usize = nfa
+            .delta
+            .values()
+            .map(|transitions| {
+                transitions.epsilon().len()
+                    + transitions
+                        .iter()
+                        .map(|(_, _, set)| set.len())
+                        .sum::<usize>()
+            })
+            .sum();
+        assert!(edges <= 2 * nfa.states);
+        let nfa = repeat(&optional(&unit('a')), 0, Some(300));
+        assert!(epsilon_edges(&nfa) <= 2 * nfa.states);
+        assert!(accepts(&nfa, &"a".repeat(300)));
+        assert!(!accepts(&nfa, &"a".repeat(301)));
+    }
+
+    #[test]
+    pub fn test_with_looks() {
+        let nfa = concat_all(&[&unit('a'), &look(Look::WordBoundary), &unit(' ')]);
+        assert!(accepts(&nfa, "a "));
+        let nfa = star(&plus(&unit('a'), &look(Look::WordBoundary)));
+        assert!(accepts(&nfa, "aa"));
+        assert!(nfa.find("  a").is_some());
+        assert_eq!(nfa.find("  aa").map(|m| m.range()), Some(0..0));
+    }
+}
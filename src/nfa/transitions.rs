@@ -0,0 +1,305 @@
+use super::class::{pred, succ, Class};
+use super::look::{Look, Side};
+use super::node::Node;
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+
+/// The outgoing edges of one node: sorted, disjoint inclusive `char` ranges,
+/// each labelled with the nodes it leads to, plus zero-width edges that may
+/// only be taken where their `Look` holds, and epsilon edges that may always
+/// be taken without reading a char. A char in none of the ranges has no edge.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Transitions {
+    ranges: Vec<(char, char, HashSet<Node>)>,
+    looks: HashMap<Look, HashSet<Node>>,
+    epsilon: HashSet<Node>,
+}
+
+impl Transitions {
+    pub fn new() -> Transitions {
+        Transitions::default()
+    }
+
+    /// Edges from every char in `class` to `nodes`.
+    pub fn from_class(class: &Class, nodes: &HashSet<Node>) -> Transitions {
+        let ranges = if nodes.is_empty() {
+            vec![]
+        } else {
+            class
+                .ranges()
+                .iter()
+                .map(|&(lo, hi)| (lo, hi, nodes.clone()))
+                .collect()
+        };
+        Transitions {
+            ranges,
+            ..Transitions::default()
+        }
+    }
+
+    /// Edges on ranges that are already sorted and disjoint, as `iter` yields
+    /// them.
+    pub fn from_ranges<I: IntoIterator<Item = (char, char, HashSet<Node>)>>(
+        ranges: I,
+    ) -> Transitions {
+        let ranges: Vec<(char, char, HashSet<Node>)> = ranges
+            .into_iter()
+            .filter(|(_, _, set)| !set.is_empty())
+            .collect();
+        debug_assert!(ranges.windows(2).all(|pair| pair[0].1 < pair[1].0));
+        Transitions {
+            ranges: merge_adjacent(ranges),
+            ..Transitions::default()
+        }
+    }
+
+    /// A zero-width edge to `nodes`, taken only where `look` holds.
+    pub fn from_look(look: Look, nodes: &HashSet<Node>) -> Transitions {
+        let mut transitions = Transitions::new();
+        transitions.insert_look(look, nodes);
+        transitions
+    }
+
+    /// Epsilon edges to `nodes`.
+    pub fn from_epsilon(nodes: &HashSet<Node>) -> Transitions {
+        let mut transitions = Transitions::new();
+        transitions.insert_epsilon(nodes);
+        transitions
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ranges.is_empty() && self.looks.is_empty() && self.epsilon.is_empty()
+    }
+
+    /// The nodes reached on `ch`, found by binary search over the ranges.
+    pub fn get(&self, ch: char) -> Option<&HashSet<Node>> {
+        self.ranges
+            .binary_search_by(|&(lo, hi, _)| {
+                if hi < ch {
+                    Ordering::Less
+                } else if lo > ch {
+                    Ordering::Greater
+                } else {
+                    Ordering::Equal
+                }
+            })
+            .ok()
+            .map(|i| &self.ranges[i].2)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (char, char, &HashSet<Node>)> {
+        self.ranges.iter().map(|(lo, hi, set)| (*lo, *hi, set))
+    }
+
+    pub fn looks(&self) -> impl Iterator<Item = (Look, &HashSet<Node>)> {
+        self.looks.iter().map(|(look, set)| (*look, set))
+    }
+
+    pub fn epsilon(&self) -> &HashSet<Node> {
+        &self.epsilon
+    }
+
+    /// The nodes that can be reached without reading a char at a position
+    /// between chars of the kinds `before` and `after`: every epsilon edge,
+    /// and the zero-width edges whose `Look` holds there.
+    pub fn zero_width(&self, before: Side, after: Side) -> impl Iterator<Item = Node> + '_ {
+        self.looks
+            .iter()
+            .filter(move |(look, _)| look.holds_between(before, after))
+            .flat_map(|(_, set)| set)
+            .chain(&self.epsilon)
+            .copied()
+    }
+
+    /// Adds edges to `nodes` on every char in `lo..=hi`, splitting existing
+    /// ranges where they only partly overlap.
+    pub fn insert(&mut self, lo: char, hi: char, nodes: &HashSet<Node>) {
+        self.union(&Transitions {
+            ranges: vec![(lo, hi, nodes.clone())],
+            ..Transitions::default()
+        });
+    }
+
+    pub fn insert_look(&mut self, look: Look, nodes: &HashSet<Node>) {
+        if !nodes.is_empty() {
+            self.looks
+                .entry(look)
+                .or_default()
+                .extend(nodes.iter().copied());
+        }
+    }
+
+    pub fn insert_epsilon(&mut self, nodes: &HashSet<Node>) {
+        self.epsilon.extend(nodes.iter().copied());
+    }
+
+    pub fn clear_epsilon(&mut self) {
+        self.epsilon.clear();
+    }
+
+    /// Adds every edge of `other` to this node.
+    pub fn union(&mut self, other: &Transitions) {
+        for (look, set) in other.looks() {
+            self.insert_look(look, set);
+        }
+        self.insert_epsilon(&other.epsilon);
+        if other.ranges.is_empty() {
+            return;
+        }
+        // every point where the union of the two might change
+        let mut bounds: Vec<char> = vec![];
+        for &(lo, hi, _) in self.ranges.iter().chain(other.ranges.iter()) {
+            bounds.push(lo);
+            bounds.extend(succ(hi));
+        }
+        bounds.sort();
+        bounds.dedup();
+        let mut ranges = vec![];
+        for (i, &lo) in bounds.iter().enumerate() {
+            let hi = match bounds.get(i + 1) {
+                Some(&next) => pred(next).expect("bound after another bound"),
+                None => char::MAX,
+            };
+            let set: HashSet<Node> = self
+                .get(lo)
+                .into_iter()
+                .chain(other.get(lo))
+                .flatten()
+                .copied()
+                .collect();
+            if !set.is_empty() {
+                ranges.push((lo, hi, set));
+            }
+        }
+        self.ranges = merge_adjacent(ranges);
+    }
+
+    /// Replaces the target set of every edge with `f` of it.
+    pub fn map<F: Fn(&HashSet<Node>) -> HashSet<Node>>(&self, f: F) -> Transitions {
+        let ranges = self
+            .ranges
+            .iter()
+            .map(|(lo, hi, set)| (*lo, *hi, f(set)))
+            .filter(|(_, _, set)| !set.is_empty())
+            .collect();
+        let looks = self
+            .looks
+            .iter()
+            .map(|(look, set)| (*look, f(set)))
+            .filter(|(_, set)| !set.is_empty())
+            .collect();
+        let epsilon = if self.epsilon.is_empty() {
+            HashSet::new()
+        } else {
+            f(&self.epsilon)
+        };
+        Transitions {
+            ranges: merge_adjacent(ranges),
+            looks,
+            epsilon,
+        }
+    }
+}
+
+/// Joins neighbouring ranges that touch and lead to the same nodes.
+fn merge_adjacent(ranges: Vec<(char, char, HashSet<Node>)>) -> Vec<(char, char, HashSet<Node>)> {
+    let mut merged: Vec<(char, char, HashSet<Node>)> = Vec::with_capacity(ranges.len());
+    for (lo, hi, set) in ranges {
+        match merged.last_mut() {
+            Some((_, last, last_set)) if succ(*last) == Some(lo) && *last_set == set => {
+                *last = hi;
+            }
+            _ => merged.push((lo, hi, set)),
+        }
+    }
+    merged
+}
+
+#[cfg(test)]
+mod test {
+    use crate::nfa::transitions::*;
+
+    fn nodes(ns: &[usize]) -> HashSet<Node> {
+        ns.iter().map(|&n| Node(n)).collect()
+    }
+
+    #[test]
+    pub fn test_get() {
+        let transitions =
+            Transitions::from_class(&Class::new([('a', 'c'), ('x', 'z')]), &nodes(&[1]));
+        assert_eq!(transitions.get('b'), Some(&nodes(&[1])));
+        assert_eq!(transitions.get('z'), Some(&nodes(&[1])));
+        assert_eq!(transitions.get('d'), None);
+    }
+
+    #[test]
+    pub fn test_insert_splits() {
+        let mut transitions = Transitions::new();
+        transitions.insert('a', 'm', &nodes(&[1]));
+        transitions.insert('f', 'z', &nodes(&[2]));
+        let ranges: Vec<_> = transitions
+            .iter()
+            .map(|(lo, hi, set)| (lo, hi, set.clone()))
+            .collect();
+        assert_eq!(
+            ranges,
+            vec![
+                ('a', 'e', nodes(&[1])),
+                ('f', 'm', nodes(&[1, 2])),
+                ('n', 'z', nodes(&[2])),
+            ]
+        );
+    }
+
+    #[test]
+    pub fn test_insert_merges() {
+        let mut transitions = Transitions::new();
+        transitions.insert('a', 'c', &nodes(&[1]));
+        transitions.insert('d', 'f', &nodes(&[1]));
+        transitions.insert('\u{D7FF}', '\u{D7FF}', &nodes(&[1]));
+        transitions.insert('\u{E000}', char::MAX, &nodes(&[1]));
+        assert_eq!(transitions.iter().count(), 2);
+        assert_eq!(transitions.get(char::MAX), Some(&nodes(&[1])));
+    }
+
+    #[test]
+    pub fn test_map() {
+        let mut transitions = Transitions::new();
+        transitions.insert('a', 'c', &nodes(&[1]));
+        transitions.insert('d', 'f', &nodes(&[2]));
+        let mapped = transitions.map(|_| nodes(&[3]));
+        assert_eq!(mapped.iter().count(), 1);
+        assert!(transitions.map(|_| nodes(&[])).is_empty());
+    }
+
+    #[test]
+    pub fn test_from_ranges() {
+        let transitions = Transitions::from_ranges([
+            ('a', 'b', nodes(&[1])),
+            ('c', 'c', nodes(&[1])),
+            ('e', 'f', nodes(&[])),
+            ('x', 'z', nodes(&[2])),
+        ]);
+        assert_eq!(transitions.iter().count(), 2);
+        assert_eq!(transitions.get('c'), Some(&nodes(&[1])));
+        assert_eq!(transitions.get('e'), None);
+    }
+
+    #[test]
+    pub fn test_looks() {
+        let mut transitions = Transitions::from_look(Look::Start, &nodes(&[1]));
+        transitions.union(&Transitions::from_look(Look::Start, &nodes(&[2])));
+        transitions.insert('a', 'a', &nodes(&[3]));
+        assert!(!transitions.is_empty());
+        assert_eq!(
+            transitions.looks().collect::<Vec<_>>(),
+            vec![(Look::Start, &nodes(&[1, 2]))]
+        );
+        let mapped = transitions.map(|set| set.iter().map(|&Node(n)| Node(n + 1)).collect());
+        assert_eq!(
+            mapped.looks().collect::<Vec<_>>(),
+            vec![(Look::Start, &nodes(&[2, 3]))]
+        );
+        assert_eq!(mapped.get('a'), Some(&nodes(&[4])));
+    }
+}
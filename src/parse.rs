@@ -0,0 +1,784 @@
+pub mod error;
+
+use crate::ast::Expr;
+use crate::nfa::class::Class;
+use crate::nfa::look::Look;
+pub use error::{ErrorKind, ParseError};
+use std::ops::Range;
+
+/// Recursive descent parser from a pattern string to an `Expr`.
+///
+/// The grammar, from loosest to tightest binding:
+///
+/// ```text
+/// alt    := inter ('|' inter)*
+/// inter  := concat ('&' concat)*
+/// concat := repeat*
+/// repeat := atom quant?
+/// quant  := '*' | '+' | '?' | '{' num (',' num?)? '}'
+/// atom   := '(' ('?' flag* ':' | '?' 'P'? '<' name '>')? alt ')'
+///         | '~(' alt ')'
+///         | '[' '^'? item* ']'
+///         | '.' | '^' | '$' | '\b' | '\B' | escape | char
+/// flag   := 's' | 'm'
+/// name   := [A-Za-z_] [A-Za-z0-9_]*
+/// item   := member ('-' member)?
+/// member := escape | char
+/// escape := '\' ('n' | 'r' | 't' | 'u{' hex+ '}' | char)
+/// ```
+///
+/// The intersection `&` and the complement `~(...)` are only available when
+/// the pattern is compiled to an automaton, and not for `Regex`, which reads
+/// `&` as a literal and rejects `~(`. A `~` not followed by `(` is always a
+/// literal.
+///
+/// A complement is built by determinizing its operand, which can take
+/// exponentially many states in the length of the pattern, as for
+/// `~((a|b)*a(a|b){20})`. `NFA::from_pattern` rejects a complement that takes
+/// more than `MAX_COMPLEMENT_STATES`.
+struct Parser<'a> {
+    pattern: &'a str,
+    pos: usize,
+    options: Options,
+    /// Whether `&` and `~(...)` may be used, rather than being errors.
+    set_operators: bool,
+    /// How many groups and complements enclose the current position.
+    depth: usize,
+    /// How many positions the pattern read so far expands to once every
+    /// repetition is written out, as the automata build it.
+    size: usize,
+    /// How many capturing groups have been opened so far.
+    groups: usize,
+    /// The names given to groups so far.
+    names: Vec<String>,
+    /// The spans of the `~(` opening each complement so far.
+    complements: Vec<Range<usize>>,
+}
+
+/// Settings that change how a pattern is read.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Options {
+    /// Whether `.` also matches `\n`. Set within a pattern by `(?s:...)`.
+    pub dot_all: bool,
+    /// Whether `^` and `$` also match just after and just before a `\n`. Set
+    /// within a pattern by `(?m:...)`.
+    pub multi_line: bool,
+}
+
+impl<'a> Parser<'a> {
+    fn new(pattern: &'a str, options: Options, set_operators: bool) -> Parser<'a> {
+        Parser {
+            pattern,
+            pos: 0,
+            options,
+            set_operators,
+            depth: 0,
+            size: 0,
+            groups: 0,
+            names: vec![],
+            complements: vec![],
+        }
+    }
+
+    /// Parses the whole pattern.
+    fn parse(&mut self) -> Result<Expr, ParseError> {
+        let expr = self.alt()?;
+        match self.next() {
+            None => Ok(expr),
+            Some(ch) => Err(self.error_here(ErrorKind::UnopenedGroup, ch)),
+        }
+    }
+
+    fn error(&self, kind: ErrorKind, span: Range<usize>) -> ParseError {
+        ParseError::new(kind, span, self.pattern)
+    }
+
+    /// An error spanning the character just consumed.
+    fn error_here(&self, kind: ErrorKind, ch: char) -> ParseError {
+        self.error(kind, self.pos - ch.len_utf8()..self.pos)
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.pattern[self.pos..].chars().next()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let ch = self.peek()?;
+        self.pos += ch.len_utf8();
+        Some(ch)
+    }
+
+    fn peek_second(&self) -> Option<char> {
+        self.pattern[self.pos..].chars().nth(1)
+    }
+
+    fn eat(&mut self, ch: char) -> bool {
+        if self.peek() == Some(ch) {
+            self.pos += ch.len_utf8();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn alt(&mut self) -> Result<Expr, ParseError> {
+        let mut exprs = vec![self.intersect()?];
+        while self.eat('|') {
+            exprs.push(self.intersect()?);
+        }
+        Ok(if exprs.len() == 1 {
+            exprs.remove(0)
+        } else {
+            Expr::Alt(exprs)
+        })
+    }
+
+    fn intersect(&mut self) -> Result<Expr, ParseError> {
+        let mut exprs = vec![self.concat()?];
+        while self.set_operators && self.eat('&') {
+            exprs.push(self.concat()?);
+        }
+        Ok(if exprs.len() == 1 {
+            exprs.remove(0)
+        } else {
+            Expr::Intersect(exprs)
+        })
+    }
+
+    fn concat(&mut self) -> Result<Expr, ParseError> {
+        let mut exprs = vec![];
+        while let Some(ch) = self.peek() {
+            if ch == '|' || ch == ')' || (ch == '&' && self.set_operators) {
+                break;
+            }
+            // a bare `(?:)` adds nothing to a concatenation
+            match self.repeat()? {
+                Expr::Empty => {}
+                expr => exprs.push(expr),
+            }
+        }
+        Ok(match exprs.len() {
+            0 => Expr::Empty,
+            1 => exprs.remove(0),
+            _ => Expr::Concat(exprs),
+        })
+    }
+
+    fn repeat(&mut self) -> Result<Expr, ParseError> {
+        let before = self.size;
+        let expr = Box::new(self.atom()?);
+        let quantifier = self.pos;
+        let (min, max) = match self.peek() {
+            Some('*') => {
+                self.next();
+                return self.no_quantifier(Expr::Star(expr));
+            }
+            Some('+') => {
+                self.next();
+                (1, None)
+            }
+            Some('?') => {
+                self.next();
+                (0, Some(1))
+            }
+            Some('{') => self.counts()?,
+            _ => return Ok(*expr),
+        };
+        // `min` copies of the operand, then `max - min` optional ones or a
+        // starred one
+        let copies = max.unwrap_or(min + 1);
+        self.size = before.saturating_add((self.size - before).saturating_mul(copies));
+        if self.size > MAX_SIZE {
+            return Err(self.error(ErrorKind::PatternTooLarge, quantifier..self.pos));
+        }
+        self.no_quantifier(Expr::Repeat { expr, min, max })
+    }
+
+    /// Rejects a quantifier directly after another one, as in `a**`.
+    fn no_quantifier(&mut self, expr: Expr) -> Result<Expr, ParseError> {
+        match self.peek() {
+            Some(ch) if is_quantifier(ch) => {
+                self.next();
+                Err(self.error_here(ErrorKind::NestedRepetition, ch))
+            }
+            _ => Ok(expr),
+        }
+    }
+
+    /// Parses a counted repetition `{m}`, `{m,}` or `{m,n}` starting at the
+    /// current `{`.
+    fn counts(&mut self) -> Result<(usize, Option<usize>), ParseError> {
+        let start = self.pos;
+        self.next();
+        let invalid =
+            |parser: &Parser| parser.error(ErrorKind::InvalidRepetition, start..parser.pos);
+        let min = self.number().ok_or_else(|| invalid(self))?;
+        let max = if !self.eat(',') {
+            Some(min)
+        } else if self.peek() == Some('}') {
+            None
+        } else {
+            Some(self.number().ok_or_else(|| invalid(self))?)
+        };
+        if !self.eat('}') {
+            return Err(invalid(self));
+        }
+        if min.max(max.unwrap_or(0)) > MAX_REPEAT {
+            return Err(self.error(ErrorKind::RepetitionTooLarge, start..self.pos));
+        }
+        if max.is_some_and(|max| max < min) {
+            return Err(self.error(ErrorKind::InvalidRepetitionRange, start..self.pos));
+        }
+        Ok((min, max))
+    }
+
+    /// Parses a decimal number, saturating on overflow, or returns `None` if
+    /// there are no digits.
+    fn number(&mut self) -> Option<usize> {
+        let start = self.pos;
+        while self.peek().is_some_and(|ch| ch.is_ascii_digit()) {
+            self.next();
+        }
+        let digits = &self.pattern[start..self.pos];
+        (!digits.is_empty()).then(|| digits.parse().unwrap_or(usize::MAX))
+    }
+
+    fn atom(&mut self) -> Result<Expr, ParseError> {
+        let start = self.pos;
+        // callers only ask for an atom when there is input left
+        let ch = self.next().expect("atom at end of pattern");
+        let expr = match ch {
+            '(' => {
+                return self.nested(start, |parser| {
+                    if !parser.eat('?') {
+                        return parser.group(start, None);
+                    }
+                    match parser.group_name()? {
+                        Some(name) => parser.group(start, Some(name)),
+                        None => parser.flag_group(start),
+                    }
+                })
+            }
+            '~' if self.eat('(') => {
+                if !self.set_operators {
+                    return Err(self.error(ErrorKind::UnsupportedOperator, start..self.pos));
+                }
+                return self.nested(start, |parser| parser.complement(start));
+            }
+            '[' => self.class(start)?,
+            '.' => Expr::Any {
+                dot_all: self.options.dot_all,
+            },
+            '^' if self.options.multi_line => Expr::Look(Look::StartLine),
+            '^' => Expr::Look(Look::Start),
+            '$' if self.options.multi_line => Expr::Look(Look::EndLine),
+            '$' => Expr::Look(Look::End),
+            '\\' if self.eat('b') => Expr::Look(Look::WordBoundary),
+            '\\' if self.eat('B') => Expr::Look(Look::NotWordBoundary),
+            '\\' => Expr::Literal(self.escape()?),
+            ch if is_quantifier(ch) => {
+                return Err(self.error_here(ErrorKind::MissingRepetitionOperand, ch));
+            }
+            ch => Expr::Literal(ch),
+        };
+        // every atom other than a group is a single position
+        self.size += 1;
+        Ok(expr)
+    }
+
+    /// Parses what `parse` reads inside an opening just consumed at `start`
+    /// one level deeper, or fails if that is past `MAX_NESTING`. Bounding
+    /// the depth keeps this parser, and everything that walks the `Expr`
+    /// recursively, from overflowing the stack.
+    fn nested<F>(&mut self, start: usize, parse: F) -> Result<Expr, ParseError>
+    where
+        F: FnOnce(&mut Parser<'a>) -> Result<Expr, ParseError>,
+    {
+        if self.depth == MAX_NESTING {
+            return Err(self.error(ErrorKind::NestTooDeep, start..self.pos));
+        }
+        self.depth += 1;
+        let expr = parse(self);
+        self.depth -= 1;
+        expr
+    }
+
+    /// Parses the rest of a capturing group whose `(` starts at `start`.
+    fn group(&mut self, start: usize, name: Option<String>) -> Result<Expr, ParseError> {
+        self.groups += 1;
+        let index = self.groups;
+        let expr = Box::new(self.alt()?);
+        if self.eat(')') {
+            Ok(Expr::Group { index, name, expr })
+        } else {
+            Err(self.error(ErrorKind::UnclosedGroup, start..self.pos))
+        }
+    }
+
+    /// Parses the rest of a complement `~(...)` whose `~` starts at `start`.
+    fn complement(&mut self, start: usize) -> Result<Expr, ParseError> {
+        self.complements.push(start..self.pos);
+        let expr = Box::new(self.alt()?);
+        if self.eat(')') {
+            Ok(Expr::Complement(expr))
+        } else {
+            Err(self.error(ErrorKind::UnclosedGroup, start..self.pos))
+        }
+    }
+
+    /// Parses the `P<name>` or `<name>` after a `(?`, or returns `None` if the
+    /// group is not named.
+    fn group_name(&mut self) -> Result<Option<String>, ParseError> {
+        if self.peek() == Some('P') && self.peek_second() == Some('<') {
+            self.next();
+        }
+        if !self.eat('<') {
+            return Ok(None);
+        }
+        let start = self.pos;
+        while self.peek().is_some_and(|ch| ch != '>' && ch != ')') {
+            self.next();
+        }
+        let name = &self.pattern[start..self.pos];
+        let valid = name.starts_with(|ch: char| ch.is_ascii_alphabetic() || ch == '_')
+            && name
+                .chars()
+                .all(|ch| ch.is_ascii_alphanumeric() || ch == '_');
+        if !valid || !self.eat('>') {
+            return Err(self.error(ErrorKind::InvalidGroupName, start..self.pos));
+        }
+        let span = start..self.pos - 1;
+        if self.names.iter().any(|other| other == name) {
+            return Err(self.error(ErrorKind::DuplicateGroupName, span));
+        }
+        self.names.push(String::from(name));
+        Ok(Some(String::from(name)))
+    }
+
+    /// Parses the rest of a non-capturing group `(?flags:...)` whose `(`
+    /// starts at `start`, reading its contents with the flags turned on.
+    fn flag_group(&mut self, start: usize) -> Result<Expr, ParseError> {
+        let outer = self.options;
+        loop {
+            match self.next() {
+                Some('s') => self.options.dot_all = true,
+                Some('m') => self.options.multi_line = true,
+                Some(':') => break,
+                Some(ch) => return Err(self.error_here(ErrorKind::InvalidFlag, ch)),
+                None => return Err(self.error(ErrorKind::UnclosedGroup, start..self.pos)),
+            }
+        }
+        let expr = self.alt();
+        self.options = outer;
+        let expr = expr?;
+        if self.eat(')') {
+            Ok(expr)
+        } else {
+            Err(self.error(ErrorKind::UnclosedGroup, start..self.pos))
+        }
+    }
+
+    /// Parses the rest of a class whose `[` starts at `start`.
+    fn class(&mut self, start: usize) -> Result<Expr, ParseError> {
+        let negated = self.eat('^');
+        let mut ranges = vec![];
+        loop {
+            let item = self.pos;
+            let lo = match self.class_member() {
+                Some(lo) => lo?,
+                None if self.eat(']') => break,
+                None => return Err(self.error(ErrorKind::UnclosedClass, start..self.pos)),
+            };
+            // a '-' just before the closing ']' is a literal
+            let hi = if self.peek() == Some('-') && !matches!(self.peek_second(), Some(']') | None)
+            {
+                self.next();
+                self.class_member().expect("class member after '-'")?
+            } else {
+                lo
+            };
+            if lo > hi {
+                return Err(self.error(ErrorKind::InvalidRange, item..self.pos));
+            }
+            ranges.push((lo, hi));
+        }
+        Ok(Expr::Class {
+            negated,
+            class: Class::new(ranges),
+        })
+    }
+
+    /// Parses a char inside a class, or returns `None` at the closing `]` or
+    /// the end of the pattern.
+    fn class_member(&mut self) -> Option<Result<char, ParseError>> {
+        match self.peek()? {
+            ']' => None,
+            '\\' => {
+                self.next();
+                Some(self.escape())
+            }
+            ch => {
+                self.next();
+                Some(Ok(ch))
+            }
+        }
+    }
+
+    /// Parses the rest of an escape sequence whose `\` was just consumed.
+    fn escape(&mut self) -> Result<char, ParseError> {
+        let start = self.pos - 1;
+        match self.next() {
+            None => Err(self.error(ErrorKind::TrailingBackslash, start..self.pos)),
+            Some('n') => Ok('\n'),
+            Some('r') => Ok('\r'),
+            Some('t') => Ok('\t'),
+            Some('u') => {
+                let invalid =
+                    |parser: &Parser| parser.error(ErrorKind::InvalidEscape, start..parser.pos);
+                if !self.eat('{') {
+                    return Err(invalid(self));
+                }
+                let digits = self.pos;
+                while self.peek().is_some_and(|ch| ch.is_ascii_hexdigit()) {
+                    self.next();
+                }
+                let value = u32::from_str_radix(&self.pattern[digits..self.pos], 16).ok();
+                if !self.eat('}') {
+                    return Err(invalid(self));
+                }
+                value.and_then(char::from_u32).ok_or_else(|| invalid(self))
+            }
+            Some(ch) => Ok(ch),
+        }
+    }
+}
+
+/// The largest count allowed in a counted repetition, so that a short pattern
+/// cannot compile into an enormous automaton.
+pub const MAX_REPEAT: usize = 1000;
+
+/// The most positions a pattern may expand to once every repetition is
+/// written out. `MAX_REPEAT` bounds a single count, but nested repetitions
+/// multiply, so this bounds them all together. Every construction builds a
+/// repetition with nodes and edges linear in the copies it is made of, so
+/// this bounds the size of the automaton as well.
+pub const MAX_SIZE: usize = 100_000;
+
+/// The deepest groups and complements may be nested in one another.
+pub const MAX_NESTING: usize = 250;
+
+/// The most states the operand of a complement may determinize into when a
+/// pattern is compiled. Determinizing can blow up exponentially, so this keeps
+/// a short pattern from taking minutes to compile.
+pub const MAX_COMPLEMENT_STATES: usize = 10_000;
+
+fn is_quantifier(ch: char) -> bool {
+    matches!(ch, '*' | '+' | '?' | '{')
+}
+
+/// Parses `pattern` into an `Expr` with the default `Options`.
+pub fn parse(pattern: &str) -> Result<Expr, ParseError> {
+    parse_with(pattern, Options::default())
+}
+
+pub fn parse_with(pattern: &str, options: Options) -> Result<Expr, ParseError> {
+    parse_complements(pattern, options).map(|(expr, _)| expr)
+}
+
+/// Same as `parse_with`, along with the span of the `~(` of every complement,
+/// in the order they appear in the pattern.
+pub(crate) fn parse_complements(
+    pattern: &str,
+    options: Options,
+) -> Result<(Expr, Vec<Range<usize>>), ParseError> {
+    let mut parser = Parser::new(pattern, options, true);
+    let expr = parser.parse()?;
+    Ok((expr, parser.complements))
+}
+
+/// Parses `pattern` as read by `Regex`, which has no set operators: `&` is a
+/// literal and `~(` an error.
+pub fn parse_regex(pattern: &str, options: Options) -> Result<Expr, ParseError> {
+    Parser::new(pattern, options, false).parse()
+}
+
+#[cfg(test)]
+mod test {
+    use crate::nfa::search::Match;
+    use crate::nfa::NFA;
+    use crate::parse::*;
+    use char_stream::CharStream;
+
+    fn matches(nfa: &NFA, s: &str) -> bool {
+        nfa.is_match(&mut CharStream::from(s))
+    }
+
+    fn error(pattern: &str) -> (ErrorKind, Range<usize>) {
+        let error = parse(pattern).unwrap_err();
+        (error.kind(), error.span())
+    }
+
+    #[test]
+    pub fn test_literal() {
+        let nfa = NFA::from_pattern("abc").unwrap();
+        assert!(matches(&nfa, "abc"));
+        assert!(!matches(&nfa, "ab"));
+        assert!(!matches(&nfa, "abcd"));
+    }
+
+    #[test]
+    pub fn test_empty_pattern() {
+        let nfa = NFA::from_pattern("").unwrap();
+        assert!(matches(&nfa, ""));
+        assert!(!matches(&nfa, "a"));
+    }
+
+    #[test]
+    pub fn test_alternation() {
+        let nfa = NFA::from_pattern("ab|c|").unwrap();
+        assert!(matches(&nfa, "ab"));
+        assert!(matches(&nfa, "c"));
+        assert!(matches(&nfa, ""));
+        assert!(!matches(&nfa, "abc"));
+    }
+
+    #[test]
+    pub fn test_group_and_star() {
+        let nfa = NFA::from_pattern("(ab|c)*d").unwrap();
+        assert!(matches(&nfa, "d"));
+        assert!(matches(&nfa, "abd"));
+        assert!(matches(&nfa, "cabcd"));
+        assert!(!matches(&nfa, "ad"));
+        assert!(!matches(&nfa, "abc"));
+    }
+
+    #[test]
+    pub fn test_nested_star() {
+        let nfa = NFA::from_pattern("((ab)*c)*").unwrap();
+        assert!(matches(&nfa, ""));
+        assert!(matches(&nfa, "ababcc"));
+        assert!(!matches(&nfa, "ab"));
+        assert!(!matches(&nfa, "abcab"));
+    }
+
+    #[test]
+    pub fn test_escape() {
+        let nfa = NFA::from_pattern(r"\(\*\\").unwrap();
+        assert!(matches(&nfa, r"(*\"));
+        assert!(!matches(&nfa, "("));
+    }
+
+    #[test]
+    pub fn test_class() {
+        let nfa = NFA::from_pattern("[a-z0-9_]*").unwrap();
+        assert!(matches(&nfa, "snake_case_42"));
+        assert!(!matches(&nfa, "camelCase"));
+        let nfa = NFA::from_pattern("[-a]b[a-]").unwrap();
+        assert!(matches(&nfa, "-b-"));
+        assert!(matches(&nfa, "aba"));
+        assert!(!matches(&nfa, "bba"));
+    }
+
+    #[test]
+    pub fn test_negated_class() {
+        let nfa = NFA::from_pattern(r"[^\n\]]").unwrap();
+        assert!(matches(&nfa, "a"));
+        assert!(matches(&nfa, "\u{10FFFF}"));
+        assert!(!matches(&nfa, "\n"));
+        assert!(!matches(&nfa, "]"));
+        let nfa = NFA::from_pattern("[]|[^]").unwrap();
+        assert!(matches(&nfa, "x"));
+        assert!(!matches(&nfa, ""));
+    }
+
+    #[test]
+    pub fn test_unicode_escape() {
+        let nfa = NFA::from_pattern(r"\u{1F600}[\u{41}-\u{43}]").unwrap();
+        assert!(matches(&nfa, "😀B"));
+        assert!(!matches(&nfa, "😀D"));
+    }
+
+    #[test]
+    pub fn test_any() {
+        let nfa = NFA::from_pattern("ERROR.*timeout").unwrap();
+        assert!(matches(&nfa, "ERROR: read timeout"));
+        assert!(!matches(&nfa, "ERROR: read\ntimeout"));
+        let dot_all = Options {
+            dot_all: true,
+            ..Options::default()
+        };
+        let nfa = NFA::from_pattern_with("ERROR.*timeout", dot_all).unwrap();
+        assert!(matches(&nfa, "ERROR: read\ntimeout"));
+        let nfa = NFA::from_pattern(r"[.]\.").unwrap();
+        assert!(matches(&nfa, ".."));
+        assert!(!matches(&nfa, ".a"));
+    }
+
+    #[test]
+    pub fn test_repetition() {
+        let nfa = NFA::from_pattern("[A-Z]{3}-[0-9]{4,6}").unwrap();
+        assert!(matches(&nfa, "ABC-1234"));
+        assert!(matches(&nfa, "XYZ-123456"));
+        assert!(!matches(&nfa, "AB-1234"));
+        assert!(!matches(&nfa, "ABC-123"));
+        assert!(!matches(&nfa, "ABC-1234567"));
+        let nfa = NFA::from_pattern("a+b?c{2,}").unwrap();
+        assert!(matches(&nfa, "acc"));
+        assert!(matches(&nfa, "aabcccc"));
+        assert!(!matches(&nfa, "bcc"));
+        assert!(!matches(&nfa, "abbcc"));
+        assert!(!matches(&nfa, "abc"));
+    }
+
+    #[test]
+    pub fn test_anchors() {
+        let nfa = NFA::from_pattern("^ab$").unwrap();
+        assert!(matches(&nfa, "ab"));
+        assert_eq!(nfa.find("xab\nab"), None);
+        let multi_line = Options {
+            multi_line: true,
+            ..Options::default()
+        };
+        let nfa = NFA::from_pattern_with("^ab$", multi_line).unwrap();
+        let found: Vec<_> = nfa.find_iter("ab\nab\nxab").map(|m| m.start).collect();
+        assert_eq!(found, vec![0, 3]);
+        let nfa = NFA::from_pattern("a(?m:$\n^)b").unwrap();
+        assert!(matches(&nfa, "a\nb"));
+        let nfa = NFA::from_pattern("(?s:.)$|^.").unwrap();
+        assert!(matches(&nfa, "\n"));
+    }
+
+    #[test]
+    pub fn test_word_boundaries() {
+        let nfa = NFA::from_pattern(r"\bcat\b").unwrap();
+        assert_eq!(nfa.find("concat cat"), Some(Match { start: 7, end: 10 }));
+        assert!(!nfa.is_match_anywhere("concatenate"));
+        let nfa = NFA::from_pattern(r"\Bcat").unwrap();
+        assert_eq!(nfa.find("cat concat"), Some(Match { start: 7, end: 10 }));
+        let nfa = NFA::from_pattern(r"\b").unwrap();
+        let found: Vec<_> = nfa.find_iter("ab cd").map(|m| m.start).collect();
+        assert_eq!(found, vec![0, 2, 3, 5]);
+    }
+
+    #[test]
+    pub fn test_intersection() {
+        let nfa = NFA::from_pattern("[a-z_][a-z0-9_]*&.{0,16}").unwrap();
+        assert!(matches(&nfa, "snake_case_2"));
+        assert!(!matches(&nfa, "2fast"));
+        assert!(!matches(&nfa, "much_too_long_to_match"));
+        // binds looser than concatenation and tighter than alternation
+        let nfa = NFA::from_pattern("ab&a.|c").unwrap();
+        assert!(matches(&nfa, "ab"));
+        assert!(matches(&nfa, "c"));
+        assert!(!matches(&nfa, "ac"));
+        let nfa = NFA::from_pattern(r"[&]\&&.*").unwrap();
+        assert!(matches(&nfa, "&&"));
+        assert_eq!(
+            parse_regex("ab&c", Options::default()),
+            Ok(Expr::Concat(vec![
+                Expr::Literal('a'),
+                Expr::Literal('b'),
+                Expr::Literal('&'),
+                Expr::Literal('c'),
+            ]))
+        );
+    }
+
+    #[test]
+    pub fn test_complement() {
+        let nfa = NFA::from_pattern("~(.*password.*)").unwrap();
+        assert!(matches(&nfa, "hunter2"));
+        assert!(matches(&nfa, ""));
+        assert!(!matches(&nfa, "my password"));
+        let nfa = NFA::from_pattern("[a-z]+&~(.*(ab|ba).*)").unwrap();
+        assert!(matches(&nfa, "aacbb"));
+        assert!(!matches(&nfa, "cab"));
+        assert!(!matches(&nfa, "a-c"));
+        let nfa = NFA::from_pattern("a~b").unwrap();
+        assert!(matches(&nfa, "a~b"));
+        assert_eq!(error("~(a"), (ErrorKind::UnclosedGroup, 0..3));
+        assert_eq!(
+            parse_regex("a~(b)", Options::default()).unwrap_err().span(),
+            1..3
+        );
+    }
+
+    #[test]
+    pub fn test_errors() {
+        assert_eq!(error("(ab|"), (ErrorKind::UnclosedGroup, 0..4));
+        assert_eq!(error("a(b(c)"), (ErrorKind::UnclosedGroup, 1..6));
+        assert_eq!(error("ab)c"), (ErrorKind::UnopenedGroup, 2..3));
+        assert_eq!(error("*a"), (ErrorKind::MissingRepetitionOperand, 0..1));
+        assert_eq!(error("a|*"), (ErrorKind::MissingRepetitionOperand, 2..3));
+        assert_eq!(error("a**"), (ErrorKind::NestedRepetition, 2..3));
+        assert_eq!(error("a{2}+"), (ErrorKind::NestedRepetition, 4..5));
+        assert_eq!(error("(+)"), (ErrorKind::MissingRepetitionOperand, 1..2));
+        assert_eq!(error("a{x}"), (ErrorKind::InvalidRepetition, 1..2));
+        assert_eq!(error("a{2,"), (ErrorKind::InvalidRepetition, 1..4));
+        assert_eq!(error("a{3,2}"), (ErrorKind::InvalidRepetitionRange, 1..6));
+        assert_eq!(error("a{1001}"), (ErrorKind::RepetitionTooLarge, 1..7));
+        assert_eq!(
+            error("a{99999999999999999999999}"),
+            (ErrorKind::RepetitionTooLarge, 1..26)
+        );
+        assert_eq!(error("a\\"), (ErrorKind::TrailingBackslash, 1..2));
+        assert_eq!(error("(?x:a)"), (ErrorKind::InvalidFlag, 2..3));
+        assert_eq!(error("(?m"), (ErrorKind::UnclosedGroup, 0..3));
+        assert_eq!(error("(?P<1st>a)"), (ErrorKind::InvalidGroupName, 4..7));
+        assert_eq!(error("(?<>a)"), (ErrorKind::InvalidGroupName, 3..3));
+        assert_eq!(error("(?<a-b>a)"), (ErrorKind::InvalidGroupName, 3..6));
+        assert_eq!(error("(?<ab"), (ErrorKind::InvalidGroupName, 3..5));
+        assert_eq!(
+            error("(?<a>x)(?P<a>y)"),
+            (ErrorKind::DuplicateGroupName, 11..12)
+        );
+        assert_eq!(error("(?Pa)"), (ErrorKind::InvalidFlag, 2..3));
+        assert_eq!(error("(?m:a"), (ErrorKind::UnclosedGroup, 0..5));
+        assert_eq!(error("a[bc"), (ErrorKind::UnclosedClass, 1..4));
+        assert_eq!(error("[a-"), (ErrorKind::UnclosedClass, 0..3));
+        assert_eq!(error("[az-a]"), (ErrorKind::InvalidRange, 2..5));
+        assert_eq!(error(r"\u{d800}"), (ErrorKind::InvalidEscape, 0..8));
+        assert_eq!(error(r"[\u{12"), (ErrorKind::InvalidEscape, 1..6));
+    }
+
+    #[test]
+    pub fn test_size_limit() {
+        assert!(parse("(a{1000}){100}").is_ok());
+        assert!(parse("(?:ab{10}c){1000}").is_ok());
+        assert_eq!(
+            error("((a{1000}){1000}){1000}"),
+            (ErrorKind::PatternTooLarge, 10..16)
+        );
+        assert_eq!(
+            error("(ab{1000}){100}"),
+            (ErrorKind::PatternTooLarge, 10..15)
+        );
+        assert_eq!(
+            error("(x{1000}){49}(x{1000}){49}(x{1000}){3}"),
+            (ErrorKind::PatternTooLarge, 35..38)
+        );
+        assert!(parse("(a{1000}){0}(b{1000}){99}").is_ok());
+    }
+
+    #[test]
+    pub fn test_nesting_limit() {
+        let deep = "(".repeat(10000);
+        assert_eq!(error(&deep), (ErrorKind::NestTooDeep, 250..251));
+        let deepest = format!("{}a{}", "(?:".repeat(250), ")".repeat(250));
+        assert!(parse(&deepest).is_ok());
+        let too_deep = format!("{}a{}", "(?:".repeat(251), ")".repeat(251));
+        assert_eq!(error(&too_deep), (ErrorKind::NestTooDeep, 750..751));
+        assert_eq!(
+            error(&format!("{}~(a)", "(".repeat(250))),
+            (ErrorKind::NestTooDeep, 250..252)
+        );
+    }
+
+    #[test]
+    pub fn test_error_display() {
+        assert_eq!(
+            parse("x(ab|").unwrap_err().to_string(),
+            "error: unclosed group\n    x(ab|\n     ^^^^"
+        );
+    }
+}
@@ -0,0 +1,230 @@
+use crate::ast::write_char;
+use std::error::Error;
+use std::fmt;
+use std::ops::Range;
+
+/// What went wrong while parsing a pattern.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ErrorKind {
+    /// A `(` without a matching `)`.
+    UnclosedGroup,
+    /// A `)` without a matching `(`.
+    UnopenedGroup,
+    /// A repetition operator with nothing before it to repeat.
+    MissingRepetitionOperand,
+    /// A repetition operator applied directly to another repetition, as in
+    /// `a**`.
+    NestedRepetition,
+    /// A `{` that does not start a well-formed `{m}`, `{m,}` or `{m,n}`.
+    InvalidRepetition,
+    /// A counted repetition `{m,n}` with `m > n`.
+    InvalidRepetitionRange,
+    /// A counted repetition above `MAX_REPEAT`.
+    RepetitionTooLarge,
+    /// Repetitions that together expand the pattern past `MAX_SIZE`
+    /// positions, as nested counts such as `(a{1000}){1000}` multiply, or a
+    /// complement whose operand determinizes into more than
+    /// `MAX_COMPLEMENT_STATES` states.
+    PatternTooLarge,
+    /// A group or complement nested more than `MAX_NESTING` deep.
+    NestTooDeep,
+    /// A letter in `(?flags:...)` that does not name a flag.
+    InvalidFlag,
+    /// A group name that is empty, unterminated or not an identifier.
+    InvalidGroupName,
+    /// A group name already given to an earlier group.
+    DuplicateGroupName,
+    /// A `\` at the very end of the pattern.
+    TrailingBackslash,
+    /// A malformed `\u{...}` escape, or one naming a surrogate or a value past
+    /// `char::MAX`.
+    InvalidEscape,
+    /// A `[` without a matching `]`.
+    UnclosedClass,
+    /// A class range such as `z-a` whose start is after its end.
+    InvalidRange,
+    /// An operator, such as the complement `~(...)`, that the engine the
+    /// pattern is compiled for cannot run.
+    UnsupportedOperator,
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let message = match self {
+            ErrorKind::UnclosedGroup => "unclosed group",
+            ErrorKind::UnopenedGroup => "unopened group",
+            ErrorKind::MissingRepetitionOperand => "repetition operator missing expression",
+            ErrorKind::NestedRepetition => "repetition operator applied to a repetition",
+            ErrorKind::InvalidRepetition => "invalid repetition count",
+            ErrorKind::InvalidRepetitionRange => "repetition range minimum exceeds maximum",
+            ErrorKind::RepetitionTooLarge => "repetition count exceeds the limit",
+            ErrorKind::PatternTooLarge => "pattern exceeds the size limit",
+            ErrorKind::NestTooDeep => "nesting exceeds the depth limit",
+            ErrorKind::InvalidFlag => "unrecognized flag",
+            ErrorKind::InvalidGroupName => "invalid capture group name",
+            ErrorKind::DuplicateGroupName => "duplicate capture group name",
+            ErrorKind::TrailingBackslash => "incomplete escape sequence",
+            ErrorKind::InvalidEscape => "invalid unicode escape",
+            ErrorKind::UnclosedClass => "unclosed character class",
+            ErrorKind::InvalidRange => "invalid character class range",
+            ErrorKind::UnsupportedOperator => "operator not supported by this engine",
+        };
+        write!(f, "{}", message)
+    }
+}
+
+/// An error in a pattern, pointing at the byte span of the offending text.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    kind: ErrorKind,
+    span: Range<usize>,
+    pattern: String,
+}
+
+impl ParseError {
+    pub fn new(kind: ErrorKind, span: Range<usize>, pattern: &str) -> ParseError {
+        ParseError {
+            kind,
+            span,
+            pattern: String::from(pattern),
+        }
+    }
+
+    pub fn kind(&self) -> ErrorKind {
+        self.kind
+    }
+
+    /// The byte offsets into the pattern of the offending text.
+    pub fn span(&self) -> Range<usize> {
+        self.span.clone()
+    }
+
+    pub fn pattern(&self) -> &str {
+        &self.pattern
+    }
+}
+
+/// How many terminal columns `ch` takes up: two for the wide chars of East
+/// Asian scripts, none for combining marks and other zero-width chars, and
+/// one for everything else.
+fn columns(ch: char) -> usize {
+    match ch as u32 {
+        0x0300..=0x036F | 0x200B..=0x200F | 0xFE00..=0xFE0F => 0,
+        0x1100..=0x115F
+        | 0x2329..=0x232A
+        | 0x2E80..=0x303E
+        | 0x3041..=0x33FF
+        | 0x3400..=0x4DBF
+        | 0x4E00..=0x9FFF
+        | 0xA000..=0xA4CF
+        | 0xAC00..=0xD7A3
+        | 0xF900..=0xFAFF
+        | 0xFE30..=0xFE4F
+        | 0xFF00..=0xFF60
+        | 0xFFE0..=0xFFE6
+        | 0x1F300..=0x1F64F
+        | 0x1F900..=0x1F9FF
+        | 0x20000..=0x2FFFD
+        | 0x30000..=0x3FFFD => 2,
+        _ => 1,
+    }
+}
+
+/// Renders the message followed by the pattern, with carets under the
+/// offending characters. Control characters in the pattern are spelled out as
+/// escapes, so that it stays on one line and the carets line up:
+///
+/// ```text
+/// error: unclosed group
+///     (ab|
+///     ^^^^
+/// ```
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut echoed = String::new();
+        let (mut column, mut width) = (0, 0);
+        for (i, ch) in self.pattern.char_indices() {
+            let start = echoed.len();
+            write_char(&mut echoed, ch, false)?;
+            // escapes are plain ASCII, one column per byte
+            let taken = if ch.is_control() {
+                echoed.len() - start
+            } else {
+                columns(ch)
+            };
+            if i < self.span.start {
+                column += taken;
+            } else if i < self.span.end {
+                width += taken;
+            }
+        }
+        writeln!(f, "error: {}", self.kind)?;
+        writeln!(f, "    {}", echoed)?;
+        write!(f, "    {}{}", " ".repeat(column), "^".repeat(width.max(1)))
+    }
+}
+
+impl Error for ParseError {}
+
+#[cfg(test)]
+mod test {
+    use crate::parse::error::*;
+
+    #[test]
+    pub fn test_display() {
+        let error = ParseError::new(ErrorKind::NestedRepetition, 2..3, "a**");
+        assert_eq!(
+            error.to_string(),
+            "error: repetition operator applied to a repetition\n    a**\n      ^"
+        );
+    }
+
+    #[test]
+    pub fn test_display_underlines_span() {
+        // the example in the doc of `Display`
+        let error = ParseError::new(ErrorKind::UnclosedGroup, 0..4, "(ab|");
+        assert_eq!(
+            error.to_string(),
+            "error: unclosed group\n    (ab|\n    ^^^^"
+        );
+    }
+
+    #[test]
+    pub fn test_display_counts_chars() {
+        let error = ParseError::new(ErrorKind::UnopenedGroup, 4..5, "éé)");
+        assert_eq!(error.to_string(), "error: unopened group\n    éé)\n      ^");
+    }
+
+    #[test]
+    pub fn test_display_escapes_controls() {
+        let error = ParseError::new(ErrorKind::UnclosedGroup, 2..4, "a\n(b");
+        assert_eq!(
+            error.to_string(),
+            "error: unclosed group\n    a\\n(b\n       ^^"
+        );
+        let error = ParseError::new(ErrorKind::UnclosedGroup, 1..3, "\t(b");
+        assert_eq!(
+            error.to_string(),
+            "error: unclosed group\n    \\t(b\n      ^^"
+        );
+        let error = ParseError::new(ErrorKind::UnopenedGroup, 2..3, "\u{7}\n)");
+        assert!(error
+            .to_string()
+            .ends_with("\n    \\u{7}\\n)\n           ^"));
+    }
+
+    #[test]
+    pub fn test_display_wide_chars() {
+        let error = ParseError::new(ErrorKind::UnclosedGroup, 6..8, "日本(b");
+        assert_eq!(
+            error.to_string(),
+            "error: unclosed group\n    日本(b\n        ^^"
+        );
+    }
+
+    #[test]
+    pub fn test_display_empty_span() {
+        let error = ParseError::new(ErrorKind::TrailingBackslash, 1..1, "a");
+        assert!(error.to_string().ends_with("\n     ^"));
+    }
+}
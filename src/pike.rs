@@ -0,0 +1,366 @@
+use crate::ast::Expr;
+use crate::nfa::class::Class;
+use crate::nfa::look::Look;
+
+/// One instruction of a `Program`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Inst {
+    /// Consume one char in the class.
+    Class(Class),
+    /// Continue at both targets, preferring the first.
+    Split(usize, usize),
+    Jump(usize),
+    /// Record the current position in a capture slot.
+    Save(usize),
+    /// Continue only where the assertion holds.
+    Look(Look),
+    Match,
+}
+
+/// A thread of the VM: the instruction it is at and its capture slots.
+type Thread = (usize, Vec<Option<usize>>);
+
+/// A set of pcs that is cleared in constant time, so that one can be reused
+/// at every position of a search however large the program is.
+#[derive(Debug)]
+struct SparseSet {
+    /// The members, in the order they were inserted.
+    dense: Vec<usize>,
+    /// For each member, its index in `dense`. Entries of other pcs are stale
+    /// and only trusted if `dense` points back at them.
+    sparse: Vec<usize>,
+}
+
+impl SparseSet {
+    fn new(capacity: usize) -> SparseSet {
+        SparseSet {
+            dense: Vec::with_capacity(capacity),
+            sparse: vec![0; capacity],
+        }
+    }
+
+    fn contains(&self, pc: usize) -> bool {
+        self.dense.get(self.sparse[pc]) == Some(&pc)
+    }
+
+    /// Adds `pc`, returning false if it was already there.
+    fn insert(&mut self, pc: usize) -> bool {
+        if self.contains(pc) {
+            return false;
+        }
+        self.sparse[pc] = self.dense.len();
+        self.dense.push(pc);
+        true
+    }
+
+    fn clear(&mut self) {
+        self.dense.clear();
+    }
+}
+
+/// A position in the haystack, with the chars on either side of it.
+#[derive(Debug, Clone, Copy)]
+struct At {
+    pos: usize,
+    before: Option<char>,
+    after: Option<char>,
+}
+
+/// An expression compiled for a Pike VM: a list of instructions whose splits
+/// are ordered, so that simulating every thread in lockstep still yields the
+/// leftmost-first match and its capture positions.
+#[derive(Debug, Clone)]
+pub struct Program {
+    insts: Vec<Inst>,
+    /// Two slots, start and end, per group, with the whole match as group 0.
+    slots: usize,
+    /// The name of each group, if it has one.
+    names: Vec<Option<String>>,
+}
+
+impl Program {
+    /// Panics if `expr` holds an intersection or a complement, which
+    /// `parse_regex` never produces.
+    pub fn compile(expr: &Expr) -> Program {
+        let mut program = Program {
+            insts: vec![Inst::Save(0)],
+            slots: 2,
+            names: vec![None],
+        };
+        program.emit(expr);
+        program.insts.push(Inst::Save(1));
+        program.insts.push(Inst::Match);
+        program
+    }
+
+    /// How many groups the program captures, including the whole match.
+    pub fn groups(&self) -> usize {
+        self.slots / 2
+    }
+
+    /// The name of each group in order, or `None` for unnamed groups and the
+    /// whole match.
+    pub fn names(&self) -> &[Option<String>] {
+        &self.names
+    }
+
+    fn push(&mut self, inst: Inst) -> usize {
+        self.insts.push(inst);
+        self.insts.len() - 1
+    }
+
+    /// Points the second target of the split at `pc` to `target`.
+    fn patch(&mut self, pc: usize, target: usize) {
+        match &mut self.insts[pc] {
+            Inst::Split(_, second) => *second = target,
+            Inst::Jump(to) => *to = target,
+            inst => unreachable!("patching {:?}", inst),
+        }
+    }
+
+    fn emit(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Empty => {}
+            Expr::Literal(ch) => {
+                self.push(Inst::Class(Class::single(*ch)));
+            }
+            Expr::Class { negated, class } => {
+                self.push(Inst::Class(if *negated {
+                    class.negate()
+                } else {
+                    class.clone()
+                }));
+            }
+            Expr::Any { dot_all } => {
+                self.push(Inst::Class(if *dot_all {
+                    Class::full()
+                } else {
+                    Class::single('\n').negate()
+                }));
+            }
+            Expr::Look(look) => {
+                self.push(Inst::Look(*look));
+            }
+            Expr::Concat(exprs) => exprs.iter().for_each(|expr| self.emit(expr)),
+            Expr::Alt(exprs) => {
+                let mut jumps = vec![];
+                for (i, expr) in exprs.iter().enumerate() {
+                    if i + 1 == exprs.len() {
+                        self.emit(expr);
+                    } else {
+                        let split = self.push(Inst::Split(self.insts.len() + 1, 0));
+                        self.emit(expr);
+                        jumps.push(self.push(Inst::Jump(0)));
+                        let next = self.insts.len();
+                        self.patch(split, next);
+                    }
+                }
+                let end = self.insts.len();
+                jumps.into_iter().for_each(|jump| self.patch(jump, end));
+            }
+            Expr::Intersect(_) | Expr::Complement(_) => {
+                panic!("set operators cannot be compiled to a program")
+            }
+            Expr::Star(expr) => self.emit_star(expr),
+            Expr::Repeat { expr, min, max } => {
+                for _ in 0..*min {
+                    self.emit(expr);
+                }
+                match max {
+                    None => self.emit_star(expr),
+                    Some(max) => {
+                        // nested optionals, (e(e(e)?)?)?, all skipping to the end
+                        let mut splits = vec![];
+                        for _ in *min..*max {
+                            splits.push(self.push(Inst::Split(self.insts.len() + 1, 0)));
+                            self.emit(expr);
+                        }
+                        let end = self.insts.len();
+                        splits.into_iter().for_each(|split| self.patch(split, end));
+                    }
+                }
+            }
+            Expr::Group { index, name, expr } => {
+                self.slots = self.slots.max(2 * index + 2);
+                if self.names.len() <= *index {
+                    self.names.resize(index + 1, None);
+                }
+                self.names[*index] = name.clone();
+                self.push(Inst::Save(2 * index));
+                self.emit(expr);
+                self.push(Inst::Save(2 * index + 1));
+            }
+        }
+    }
+
+    fn emit_star(&mut self, expr: &Expr) {
+        let split = self.push(Inst::Split(self.insts.len() + 1, 0));
+        self.emit(expr);
+        self.push(Inst::Jump(split));
+        let end = self.insts.len();
+        self.patch(split, end);
+    }
+
+    /// Adds the thread at `pc` to `list`, following every instruction that
+    /// does not consume input, in priority order. `seen` marks the pcs already
+    /// visited at this position, which also stops empty loops.
+    fn add_thread(&self, list: &mut Vec<Thread>, seen: &mut SparseSet, thread: Thread, at: At) {
+        let mut stack = vec![thread];
+        while let Some((pc, mut slots)) = stack.pop() {
+            if !seen.insert(pc) {
+                continue;
+            }
+            match &self.insts[pc] {
+                Inst::Jump(to) => stack.push((*to, slots)),
+                Inst::Split(first, second) => {
+                    stack.push((*second, slots.clone()));
+                    stack.push((*first, slots));
+                }
+                Inst::Save(slot) => {
+                    slots[*slot] = Some(at.pos);
+                    stack.push((pc + 1, slots));
+                }
+                Inst::Look(look) => {
+                    if look.holds(at.before, at.after) {
+                        stack.push((pc + 1, slots));
+                    }
+                }
+                Inst::Class(_) | Inst::Match => list.push((pc, slots)),
+            }
+        }
+    }
+
+    /// Finds the leftmost-first match in `haystack` starting at or after the
+    /// byte offset `start`, returning the position recorded in every slot.
+    pub fn exec(&self, haystack: &str, start: usize) -> Option<Vec<Option<usize>>> {
+        let mut found: Option<Vec<Option<usize>>> = None;
+        // threads waiting to be closed at the current position, best first
+        let mut pending: Vec<Thread> = vec![];
+        let mut chars = haystack[start..].chars();
+        let mut pos = start;
+        let mut before = haystack[..start].chars().next_back();
+        let mut seen = SparseSet::new(self.insts.len());
+        loop {
+            let at = At {
+                pos,
+                before,
+                after: chars.clone().next(),
+            };
+            seen.clear();
+            let mut threads = vec![];
+            for thread in pending.drain(..) {
+                self.add_thread(&mut threads, &mut seen, thread, at);
+            }
+            // a thread starting here has the lowest priority of all
+            if found.is_none() {
+                self.add_thread(&mut threads, &mut seen, (0, vec![None; self.slots]), at);
+            }
+            for (pc, slots) in threads {
+                match &self.insts[pc] {
+                    Inst::Class(class) => {
+                        if at.after.is_some_and(|ch| class.contains(ch)) {
+                            pending.push((pc + 1, slots));
+                        }
+                    }
+                    Inst::Match => {
+                        // every thread after this one has lower priority
+                        found = Some(slots);
+                        break;
+                    }
+                    inst => unreachable!("{:?} left in a thread list", inst),
+                }
+            }
+            if pending.is_empty() && found.is_some() {
+                break;
+            }
+            let Some(ch) = chars.next() else {
+                break;
+            };
+            before = Some(ch);
+            pos += ch.len_utf8();
+        }
+        found
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::parse::parse;
+    use crate::pike::*;
+
+    fn exec(pattern: &str, haystack: &str) -> Option<Vec<Option<usize>>> {
+        Program::compile(&parse(pattern).unwrap()).exec(haystack, 0)
+    }
+
+    #[test]
+    pub fn test_sparse_set() {
+        let mut set = SparseSet::new(4);
+        assert!(set.insert(2));
+        assert!(set.insert(0));
+        assert!(!set.insert(2));
+        assert!(set.contains(0) && !set.contains(1));
+        set.clear();
+        assert!(!set.contains(2));
+        assert!(set.insert(3));
+        assert!(!set.contains(0));
+    }
+
+    #[test]
+    pub fn test_leftmost_first() {
+        assert_eq!(exec("a|ab", "xab"), Some(vec![Some(1), Some(2)]));
+        assert_eq!(exec("ab|a", "xab"), Some(vec![Some(1), Some(3)]));
+        assert_eq!(exec("a*", "baa"), Some(vec![Some(0), Some(0)]));
+        assert_eq!(exec("x", "baa"), None);
+    }
+
+    #[test]
+    pub fn test_greedy_repetition() {
+        assert_eq!(
+            exec("(a+)(a*)", "aaa"),
+            Some(vec![Some(0), Some(3), Some(0), Some(3), Some(3), Some(3)])
+        );
+        assert_eq!(
+            exec("(a{1,2})(a?)", "aaa"),
+            Some(vec![Some(0), Some(3), Some(0), Some(2), Some(2), Some(3)])
+        );
+    }
+
+    #[test]
+    pub fn test_groups() {
+        let program = Program::compile(&parse("(a)|(b)").unwrap());
+        assert_eq!(program.groups(), 3);
+        assert_eq!(program.names(), &[None, None, None]);
+        assert_eq!(
+            program.exec("b", 0),
+            Some(vec![Some(0), Some(1), None, None, Some(0), Some(1)])
+        );
+    }
+
+    #[test]
+    pub fn test_last_iteration_is_captured() {
+        assert_eq!(
+            exec("(?:(a)|b)*", "ab"),
+            Some(vec![Some(0), Some(2), Some(0), Some(1)])
+        );
+        assert_eq!(
+            exec("(a|b)*", "ab"),
+            Some(vec![Some(0), Some(2), Some(1), Some(2)])
+        );
+    }
+
+    #[test]
+    pub fn test_empty_loop_terminates() {
+        assert_eq!(
+            exec("(a*)*b", "b"),
+            Some(vec![Some(0), Some(1), None, None])
+        );
+        assert_eq!(exec("(?:^)*$", ""), Some(vec![Some(0), Some(0)]));
+    }
+
+    #[test]
+    pub fn test_looks() {
+        assert_eq!(exec(r"\bis\b", "this is"), Some(vec![Some(5), Some(7)]));
+        let program = Program::compile(&parse("^a").unwrap());
+        assert_eq!(program.exec("aa", 1), None);
+    }
+}
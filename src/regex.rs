@@ -0,0 +1,271 @@
+pub mod replace;
+pub mod split;
+
+use crate::nfa::search::{Cursor, Match};
+use crate::parse::{parse_regex, Options, ParseError};
+use crate::pike::Program;
+use std::collections::HashMap;
+use std::ops::Index;
+use std::sync::Arc;
+
+/// A compiled pattern for searching text and extracting submatches.
+///
+/// Unlike `NFA::find`, which prefers the longest of the leftmost matches, a
+/// `Regex` prefers alternatives in the order they are written and repetitions
+/// that go as far as possible, so `a|ab` finds `a` in `"ab"`.
+#[derive(Debug, Clone)]
+pub struct Regex {
+    pattern: String,
+    program: Program,
+    /// The index of every named group, shared with each `Captures`.
+    indices: Arc<HashMap<String, usize>>,
+}
+
+impl Regex {
+    pub fn new(pattern: &str) -> Result<Regex, ParseError> {
+        Regex::with_options(pattern, Options::default())
+    }
+
+    pub fn with_options(pattern: &str, options: Options) -> Result<Regex, ParseError> {
+        let expr = parse_regex(pattern, options)?;
+        let program = Program::compile(&expr);
+        let indices = program
+            .names()
+            .iter()
+            .enumerate()
+            .filter_map(|(i, name)| Some((name.clone()?, i)))
+            .collect();
+        Ok(Regex {
+            pattern: String::from(pattern),
+            program,
+            indices: Arc::new(indices),
+        })
+    }
+
+    /// The pattern this was compiled from.
+    pub fn as_str(&self) -> &str {
+        &self.pattern
+    }
+
+    /// How many groups a match captures, counting the whole match as group 0.
+    pub fn captures_len(&self) -> usize {
+        self.program.groups()
+    }
+
+    /// The name of every group in declaration order, starting with the whole
+    /// match, or `None` for groups without one.
+    pub fn capture_names(&self) -> impl Iterator<Item = Option<&str>> {
+        self.program.names().iter().map(|name| name.as_deref())
+    }
+
+    /// Finds the leftmost-first match in `haystack` along with the position
+    /// of every capturing group in it.
+    pub fn captures<'h>(&self, haystack: &'h str) -> Option<Captures<'h>> {
+        self.captures_at(haystack, 0)
+    }
+
+    /// Like `captures`, but only considers matches starting at or after the
+    /// byte offset `start`. Panics if `start` is not on a char boundary.
+    pub fn captures_at<'h>(&self, haystack: &'h str, start: usize) -> Option<Captures<'h>> {
+        let slots = self.program.exec(haystack, start)?;
+        Some(Captures {
+            haystack,
+            slots,
+            indices: self.indices.clone(),
+        })
+    }
+
+    /// Iterates over the captures of successive non-overlapping matches in
+    /// `haystack`, skipping empty matches as `NFA::find_iter` does.
+    pub fn captures_iter<'r, 'h>(&'r self, haystack: &'h str) -> CaptureMatches<'r, 'h> {
+        CaptureMatches {
+            regex: self,
+            haystack,
+            cursor: Cursor::default(),
+        }
+    }
+}
+
+/// The iterator returned by `Regex::captures_iter`.
+#[derive(Debug)]
+pub struct CaptureMatches<'r, 'h> {
+    regex: &'r Regex,
+    haystack: &'h str,
+    cursor: Cursor,
+}
+
+impl<'r, 'h> Iterator for CaptureMatches<'r, 'h> {
+    type Item = Captures<'h>;
+
+    fn next(&mut self) -> Option<Captures<'h>> {
+        self.cursor.next(self.haystack, |pos| {
+            let caps = self.regex.captures_at(self.haystack, pos)?;
+            Some((caps.get(0)?, caps))
+        })
+    }
+}
+
+/// The groups captured by one match of a `Regex`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Captures<'h> {
+    haystack: &'h str,
+    slots: Vec<Option<usize>>,
+    indices: Arc<HashMap<String, usize>>,
+}
+
+impl<'h> Captures<'h> {
+    /// Where group `i` matched, or `None` if it did not take part in the
+    /// match. Group 0 is the whole match.
+    pub fn get(&self, i: usize) -> Option<Match> {
+        match (self.slots.get(2 * i)?, self.slots.get(2 * i + 1)?) {
+            (&Some(start), &Some(end)) => Some(Match { start, end }),
+            _ => None,
+        }
+    }
+
+    /// Where the group called `name` matched, or `None` if there is no such
+    /// group or it did not take part in the match.
+    pub fn name(&self, name: &str) -> Option<Match> {
+        self.get(*self.indices.get(name)?)
+    }
+
+    /// The text group `i` matched, if it took part in the match.
+    pub fn get_str(&self, i: usize) -> Option<&'h str> {
+        self.get(i).map(|m| m.as_str(self.haystack))
+    }
+
+    /// How many groups the pattern has, counting the whole match.
+    pub fn len(&self) -> usize {
+        self.slots.len() / 2
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.slots.is_empty()
+    }
+}
+
+/// The text the group called `name` matched. Panics if there is no such group
+/// or it did not take part in the match.
+impl<'h> Index<&str> for Captures<'h> {
+    type Output = str;
+
+    fn index(&self, name: &str) -> &str {
+        self.name(name)
+            .map(|m| m.as_str(self.haystack))
+            .unwrap_or_else(|| panic!("no group named {} in the match", name))
+    }
+}
+
+/// The text group `i` matched. Panics if the group did not take part in the
+/// match.
+impl<'h> Index<usize> for Captures<'h> {
+    type Output = str;
+
+    fn index(&self, i: usize) -> &str {
+        self.get_str(i)
+            .unwrap_or_else(|| panic!("no group {} in the match", i))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::parse::ErrorKind;
+    use crate::regex::*;
+
+    #[test]
+    pub fn test_captures() {
+        let regex = Regex::new(r"([a-z]+)=([0-9]+)").unwrap();
+        assert_eq!(regex.captures_len(), 3);
+        let caps = regex.captures("ts=1234 user=42").unwrap();
+        assert_eq!(&caps[0], "ts=1234");
+        assert_eq!(&caps[1], "ts");
+        assert_eq!(&caps[2], "1234");
+        assert_eq!(caps.get(2), Some(Match { start: 3, end: 7 }));
+        let caps = regex.captures_at("ts=1234 user=42", 1).unwrap();
+        assert_eq!(&caps[0], "s=1234");
+        assert!(regex.captures("no fields").is_none());
+    }
+
+    #[test]
+    pub fn test_optional_group() {
+        let regex = Regex::new(r"(a)?(?:b|(c))").unwrap();
+        let caps = regex.captures("xb").unwrap();
+        assert_eq!(caps.len(), 3);
+        assert_eq!(caps.get(1), None);
+        assert_eq!(caps.get(2), None);
+        assert_eq!(caps.get_str(0), Some("b"));
+        assert_eq!(caps.get(3), None);
+    }
+
+    #[test]
+    pub fn test_leftmost_first() {
+        let regex = Regex::new("(a|ab)(c|bcd)").unwrap();
+        let caps = regex.captures("abcd").unwrap();
+        assert_eq!(&caps[0], "abcd");
+        assert_eq!(&caps[1], "a");
+        assert_eq!(&caps[2], "bcd");
+    }
+
+    #[test]
+    pub fn test_named_groups() {
+        let regex = Regex::new(r"(?P<user>[a-z]+)@(?<host>[a-z.]+)|(x)").unwrap();
+        let names: Vec<_> = regex.capture_names().collect();
+        assert_eq!(names, vec![None, Some("user"), Some("host"), None]);
+        let caps = regex.captures("mail root@example.org now").unwrap();
+        assert_eq!(caps.name("user"), Some(Match { start: 5, end: 9 }));
+        assert_eq!(&caps["host"], "example.org");
+        assert_eq!(caps.get(2), caps.name("host"));
+        assert_eq!(caps.name("nobody"), None);
+    }
+
+    #[test]
+    pub fn test_captures_iter() {
+        let regex = Regex::new("([a-z]+)=([0-9]*)").unwrap();
+        let pairs: Vec<_> = regex
+            .captures_iter("a=1 bc= d=234")
+            .map(|caps| (caps[1].to_string(), caps[2].to_string()))
+            .collect();
+        assert_eq!(
+            pairs,
+            vec![
+                (String::from("a"), String::from("1")),
+                (String::from("bc"), String::from("")),
+                (String::from("d"), String::from("234")),
+            ]
+        );
+        let regex = Regex::new("a*").unwrap();
+        let starts: Vec<_> = regex
+            .captures_iter("baaa")
+            .map(|caps| caps.get(0).unwrap().range())
+            .collect();
+        assert_eq!(starts, vec![0..0, 1..4]);
+    }
+
+    #[test]
+    pub fn test_ampersand_is_literal() {
+        let regex = Regex::new("&amp;").unwrap();
+        assert_eq!(
+            regex.captures("a &amp; b").unwrap().get_str(0),
+            Some("&amp;")
+        );
+        let regex = Regex::new("(a|b)&b").unwrap();
+        assert_eq!(regex.captures("x b&b").unwrap().get_str(0), Some("b&b"));
+        assert!(regex.captures("b").is_none());
+        let regex = Regex::new(r"a\&b").unwrap();
+        assert_eq!(regex.captures("x a&b").unwrap().get_str(0), Some("a&b"));
+    }
+
+    #[test]
+    pub fn test_rejects_complement() {
+        let error = Regex::new("x~(a)").unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::UnsupportedOperator);
+        assert_eq!(error.span(), 1..3);
+    }
+
+    #[test]
+    #[should_panic]
+    pub fn test_index_missing_group() {
+        let regex = Regex::new("(a)|b").unwrap();
+        let _ = &regex.captures("b").unwrap()[1];
+    }
+}
@@ -0,0 +1,154 @@
+use super::{Captures, Regex};
+
+/// Something that can produce the replacement text for a match.
+pub trait Replacer {
+    /// Appends the replacement for the match in `caps` to `dst`.
+    fn replace_append(&mut self, caps: &Captures, dst: &mut String);
+}
+
+/// A template in which `$1` or `${1}` stands for the text of group 1, `$name`
+/// or `${name}` for the text of the group called `name`, and `$$` for a
+/// literal `$`. A reference to a group that does not exist or did not take
+/// part in the match expands to nothing.
+impl Replacer for &str {
+    fn replace_append(&mut self, caps: &Captures, dst: &mut String) {
+        expand(self, caps, dst);
+    }
+}
+
+impl Replacer for &String {
+    fn replace_append(&mut self, caps: &Captures, dst: &mut String) {
+        expand(self, caps, dst);
+    }
+}
+
+/// A closure computing the replacement from the captures of each match.
+impl<F, T> Replacer for F
+where
+    F: FnMut(&Captures) -> T,
+    T: AsRef<str>,
+{
+    fn replace_append(&mut self, caps: &Captures, dst: &mut String) {
+        dst.push_str(self(caps).as_ref());
+    }
+}
+
+/// Expands the group references in `template` with the text from `caps`.
+pub fn expand(template: &str, caps: &Captures, dst: &mut String) {
+    let mut rest = template;
+    while let Some(dollar) = rest.find('$') {
+        dst.push_str(&rest[..dollar]);
+        rest = &rest[dollar + 1..];
+        if let Some(after) = rest.strip_prefix('$') {
+            dst.push('$');
+            rest = after;
+            continue;
+        }
+        let (reference, after) = match rest.strip_prefix('{') {
+            Some(braced) => match braced.find('}') {
+                Some(close) => (&braced[..close], &braced[close + 1..]),
+                None => ("", rest),
+            },
+            None if rest.starts_with(|ch: char| ch.is_ascii_digit()) => {
+                let end = rest
+                    .find(|ch: char| !ch.is_ascii_digit())
+                    .unwrap_or(rest.len());
+                rest.split_at(end)
+            }
+            None => {
+                let end = rest
+                    .find(|ch: char| !(ch.is_ascii_alphanumeric() || ch == '_'))
+                    .unwrap_or(rest.len());
+                rest.split_at(end)
+            }
+        };
+        if reference.is_empty() {
+            // not a reference after all, so the `$` is kept as written
+            dst.push('$');
+            continue;
+        }
+        let group = match reference.parse::<usize>() {
+            Ok(i) => caps.get(i),
+            Err(_) => caps.name(reference),
+        };
+        if let Some(m) = group {
+            dst.push_str(m.as_str(caps.haystack));
+        }
+        rest = after;
+    }
+    dst.push_str(rest);
+}
+
+impl Regex {
+    /// Replaces the first match in `haystack` with `rep`.
+    pub fn replace<R: Replacer>(&self, haystack: &str, rep: R) -> String {
+        self.replacen(haystack, 1, rep)
+    }
+
+    /// Replaces every non-overlapping match in `haystack` with `rep`.
+    pub fn replace_all<R: Replacer>(&self, haystack: &str, rep: R) -> String {
+        self.replacen(haystack, 0, rep)
+    }
+
+    /// Replaces the first `limit` non-overlapping matches in `haystack` with
+    /// `rep`, or every match if `limit` is 0.
+    pub fn replacen<R: Replacer>(&self, haystack: &str, limit: usize, mut rep: R) -> String {
+        let mut result = String::with_capacity(haystack.len());
+        let mut last = 0;
+        for (i, caps) in self.captures_iter(haystack).enumerate() {
+            if limit > 0 && i == limit {
+                break;
+            }
+            let m = caps.get(0).expect("group 0 always takes part");
+            result.push_str(&haystack[last..m.start]);
+            rep.replace_append(&caps, &mut result);
+            last = m.end;
+        }
+        result.push_str(&haystack[last..]);
+        result
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::regex::replace::*;
+
+    #[test]
+    pub fn test_replace() {
+        let regex = Regex::new("[0-9]+").unwrap();
+        assert_eq!(regex.replace("a1 b22 c333", "#"), "a# b22 c333");
+        assert_eq!(regex.replace_all("a1 b22 c333", "#"), "a# b# c#");
+        assert_eq!(regex.replacen("a1 b22 c333", 2, "#"), "a# b# c333");
+        assert_eq!(regex.replace_all("none", "#"), "none");
+    }
+
+    #[test]
+    pub fn test_templates() {
+        let regex = Regex::new("(?<y>[0-9]{4})-([0-9]{2})-([0-9]{2})").unwrap();
+        let date = "on 2024-03-15.";
+        assert_eq!(regex.replace(date, "$3/$2/$y"), "on 15/03/2024.");
+        assert_eq!(regex.replace(date, "${3}th ${y}"), "on 15th 2024.");
+        assert_eq!(regex.replace(date, "$$1 $9 $nope"), "on $1  .");
+        assert_eq!(regex.replace(date, "${y"), "on ${y.");
+        assert_eq!(
+            regex.replace(date, &String::from("[$0]")),
+            "on [2024-03-15]."
+        );
+    }
+
+    #[test]
+    pub fn test_closure() {
+        let regex = Regex::new("token=([a-z0-9]+)").unwrap();
+        let redacted = regex.replace_all("token=abc1 token=x", |caps: &Captures| {
+            format!("token={}", "*".repeat(caps[1].len()))
+        });
+        assert_eq!(redacted, "token=**** token=*");
+    }
+
+    #[test]
+    pub fn test_empty_matches() {
+        let regex = Regex::new("a*").unwrap();
+        assert_eq!(regex.replace_all("baaac", "-"), "-b-c-");
+        assert_eq!(regex.replace_all("", "-"), "-");
+    }
+}
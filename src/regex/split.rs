@@ -0,0 +1,125 @@
+use super::{CaptureMatches, Regex};
+
+impl Regex {
+    /// Iterates over the pieces of `haystack` between the matches, including
+    /// the possibly empty pieces before the first match and after the last.
+    pub fn split<'r, 'h>(&'r self, haystack: &'h str) -> Split<'r, 'h> {
+        Split {
+            matches: self.captures_iter(haystack),
+            haystack,
+            last: 0,
+            done: false,
+        }
+    }
+
+    /// Like `split`, but yields at most `limit` pieces, the last of which is
+    /// the rest of `haystack` after the first `limit - 1` matches.
+    pub fn splitn<'r, 'h>(&'r self, haystack: &'h str, limit: usize) -> SplitN<'r, 'h> {
+        SplitN {
+            split: self.split(haystack),
+            limit,
+        }
+    }
+}
+
+/// The iterator returned by `Regex::split`.
+#[derive(Debug)]
+pub struct Split<'r, 'h> {
+    matches: CaptureMatches<'r, 'h>,
+    haystack: &'h str,
+    /// Where the next piece starts.
+    last: usize,
+    done: bool,
+}
+
+impl<'r, 'h> Iterator for Split<'r, 'h> {
+    type Item = &'h str;
+
+    fn next(&mut self) -> Option<&'h str> {
+        if self.done {
+            return None;
+        }
+        match self.matches.next() {
+            Some(caps) => {
+                let m = caps.get(0).expect("group 0 always takes part");
+                let piece = &self.haystack[self.last..m.start];
+                self.last = m.end;
+                Some(piece)
+            }
+            None => {
+                self.done = true;
+                Some(&self.haystack[self.last..])
+            }
+        }
+    }
+}
+
+/// The iterator returned by `Regex::splitn`.
+#[derive(Debug)]
+pub struct SplitN<'r, 'h> {
+    split: Split<'r, 'h>,
+    /// How many more pieces may be yielded.
+    limit: usize,
+}
+
+impl<'r, 'h> Iterator for SplitN<'r, 'h> {
+    type Item = &'h str;
+
+    fn next(&mut self) -> Option<&'h str> {
+        match self.limit {
+            0 => None,
+            1 => {
+                self.limit = 0;
+                if self.split.done {
+                    None
+                } else {
+                    self.split.done = true;
+                    Some(&self.split.haystack[self.split.last..])
+                }
+            }
+            _ => {
+                self.limit -= 1;
+                self.split.next()
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::regex::Regex;
+
+    fn split(pattern: &str, haystack: &str) -> Vec<String> {
+        let regex = Regex::new(pattern).unwrap();
+        regex.split(haystack).map(String::from).collect()
+    }
+
+    fn splitn(pattern: &str, haystack: &str, limit: usize) -> Vec<String> {
+        let regex = Regex::new(pattern).unwrap();
+        regex.splitn(haystack, limit).map(String::from).collect()
+    }
+
+    #[test]
+    pub fn test_split() {
+        assert_eq!(split("[,;] *", "a, b;c,,d"), vec!["a", "b", "c", "", "d"]);
+        assert_eq!(split(",", ",a,"), vec!["", "a", ""]);
+        assert_eq!(split(",", "abc"), vec!["abc"]);
+        assert_eq!(split(",", ""), vec![""]);
+    }
+
+    #[test]
+    pub fn test_split_empty_matches() {
+        assert_eq!(split("x*", "abc"), vec!["", "a", "b", "c", ""]);
+    }
+
+    #[test]
+    pub fn test_splitn() {
+        assert_eq!(
+            splitn(" +", "k1 v1  rest of it", 3),
+            vec!["k1", "v1", "rest of it"]
+        );
+        assert_eq!(splitn(" +", "a b", 5), vec!["a", "b"]);
+        assert_eq!(splitn(" +", "a b", 1), vec!["a b"]);
+        assert_eq!(splitn(" +", "a b", 0), Vec::<String>::new());
+    }
+}